@@ -0,0 +1,128 @@
+extern crate rocket;
+extern crate serde;
+extern crate serde_json;
+extern crate dotenv;
+extern crate crossing_api;
+
+use dotenv::dotenv;
+use serde::Serialize;
+use std::env;
+use std::process;
+
+use crossing_api::DbConn;
+use crossing_api::lib::Admin;
+
+// Who the audit log says did it
+const ACTOR: &str = "itemxing-admin";
+
+const USAGE: &str = "Usage: itemxing-admin <command>
+
+Commands:
+  list [page]                 subscribers with most donations first
+  search <text>               subscribers whose id or profile name has the text
+  show <id>                   everything kept about a subscriber
+  delete <id> --yes           deletes a subscriber and tombstones the id
+  merge <from> <into> --yes   folds a duplicate account into another one,
+          [--force]           --force drops what can't be merged
+  repair [id] [--dry-run]     fixes malformed class values
+  role <id> <role>            sets a subscriber's role: user, moderator or admin
+  stats                       keyspace stats
+
+The database is the cross_db one from Rocket.toml or ROCKET_DATABASES";
+
+fn fail(message: &str) -> ! {
+	eprintln!("{}", message);
+	process::exit(1)
+}
+
+fn print<T: Serialize>(value: &T) {
+	match serde_json::to_string_pretty(value) {
+		Ok(value) => println!("{}", value),
+		Err(e) => fail(&e.to_string())
+	}
+}
+
+/*
+ * Asks for --yes before anything that can't be undone
+ */
+fn confirmed(args: &[String]) -> bool {
+	args.iter().any(|arg| arg == "--yes")
+}
+
+fn main() {
+	dotenv().ok();
+	let args: Vec<String> = env::args().skip(1).collect();
+	let positional: Vec<&String> = args.iter().filter(|arg| !arg.starts_with("--")).collect();
+	if positional.is_empty() {
+		fail(USAGE)
+	}
+
+	// Rocket reads the pool settings the same way the API does
+	let rocket = rocket::ignite().attach(DbConn::fairing());
+	let redis = match DbConn::get_one(&rocket) {
+		Some(redis) => redis,
+		None => fail("Couldn't connect to the database")
+	};
+
+	match (positional[0].as_str(), &positional[1..]) {
+		("list", rest) => {
+			let page = match rest.first() {
+				Some(page) => page.parse::<usize>().unwrap_or_else(|_e| fail("The page must be a number")),
+				None => 0
+			};
+			match Admin::list(&redis, page) {
+				Ok(subscribers) => print(&subscribers),
+				Err(e) => fail(&e.details)
+			}
+		},
+		("search", [text]) => match Admin::search(&redis, text) {
+			Ok(subscribers) => print(&subscribers),
+			Err(e) => fail(&e.details)
+		},
+		("show", [id]) => match Admin::show(&redis, id) {
+			Ok(data) => print(&data),
+			Err(e) => fail(&e.details)
+		},
+		("delete", [id]) => {
+			if !confirmed(&args) {
+				fail("Deleting can't be undone, run it again with --yes")
+			}
+			match Admin::delete(&redis, id) {
+				Ok(_) => {
+					let _ = Admin::audit(&redis, &ACTOR.to_string(), "delete", id, "");
+					println!("Deleted {}", id)
+				},
+				Err(e) => fail(&e.details)
+			}
+		},
+		("merge", [from, into]) => {
+			if !confirmed(&args) {
+				fail("Merging deletes the first subscriber, run it again with --yes")
+			}
+			let force = args.iter().any(|arg| arg == "--force");
+			match Admin::merge(&redis, from, into, force) {
+				Ok(report) => {
+					let _ = Admin::audit(&redis, &ACTOR.to_string(), "merge", into, from);
+					print(&report)
+				},
+				Err(e) => fail(&e.details)
+			}
+		},
+		("repair", rest) if rest.len() <= 1 => {
+			let dry_run = args.iter().any(|arg| arg == "--dry-run");
+			match Admin::repair(&redis, rest.first().map(|id| *id), dry_run) {
+				Ok(repairs) => print(&repairs),
+				Err(e) => fail(&e.details)
+			}
+		},
+		("role", [id, role]) => match Admin::set_role(&redis, &ACTOR.to_string(), id, role) {
+			Ok(_) => println!("{} is now {}", id, role),
+			Err(e) => fail(&e.details)
+		},
+		("stats", []) => match Admin::stats(&redis) {
+			Ok(stats) => print(&stats),
+			Err(e) => fail(&e.details)
+		},
+		_ => fail(USAGE)
+	}
+}
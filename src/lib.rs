@@ -0,0 +1,21 @@
+#![feature(proc_macro_hygiene, decl_macro)]
+
+#[macro_use] extern crate rocket;
+#[macro_use] extern crate rocket_contrib;
+extern crate serde;
+extern crate serde_json;
+extern crate google_signin;
+extern crate chrono;
+extern crate chrono_tz;
+extern crate zip;
+
+use rocket_contrib::databases::redis::{self};
+
+// The crate root is src/lib.rs too, so the module needs its path spelled out
+#[path = "lib/mod.rs"]
+pub mod lib;
+
+#[cfg(test)] mod tests;
+
+#[database("cross_db")]
+pub struct DbConn(redis::Connection);
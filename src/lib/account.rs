@@ -0,0 +1,178 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+
+use crate::lib::admin::Admin;
+use crate::lib::archive::DataExport;
+use crate::lib::class::Class;
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::listing::Listing;
+use crate::lib::queue::Session;
+use crate::lib::subscriber::Subscriber;
+use crate::lib::trade::{now, Trade};
+
+// The id token used to confirm a deletion can't be older than this, in seconds
+pub const REAUTH_WINDOW: u64 = 5 * 60;
+
+pub struct Account;
+
+/*
+ * A fresh id token, signing in again confirms it's really the subscriber
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Reauth {
+	pub token: String,
+}
+
+#[derive(Debug)]
+pub struct AccountError {
+	pub details: String
+}
+
+impl AccountError {
+	fn new(msg: &str) -> AccountError {
+		AccountError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for AccountError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for AccountError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+impl Account {
+
+	/*
+	 * Deleted subscriber ids and when they left, they are never handed out again
+	 */
+	pub(crate) fn tombstones_key() -> &'static str {
+		"tombstones"
+	}
+
+	/*
+	 * Whether the id token was issued recently enough to confirm a deletion
+	 */
+	pub fn is_fresh(issued_at: u64) -> bool {
+		issued_at + REAUTH_WINDOW >= now()
+	}
+
+	/*
+	 * Every key under the subscriber's id
+	 */
+	pub(crate) fn keys(redis: &DbConn, subscriber_id: &String) -> Result<Vec<String>, AccountError> {
+		match redis.scan_match::<_, String>([subscriber_id.to_owned(), ":*".to_string()].concat()) {
+			Ok(keys) => Ok(keys.collect()),
+			Err(_e) => Err(AccountError::new("Keys not found"))
+		}
+	}
+
+	/*
+	 * The tokenmap fields that point at the subscriber, one per sign in provider
+	 */
+	pub(crate) fn identities(redis: &DbConn, subscriber_id: &String) -> Result<Vec<String>, AccountError> {
+		match redis.hscan::<_, (String, String)>("tokenmap") {
+			Ok(fields) => Ok(fields
+								.filter(|(_, id)| id == subscriber_id)
+								.map(|(identity, _)| identity)
+								.collect()),
+			Err(_e) => Err(AccountError::new("Identities not found"))
+		}
+	}
+
+	/*
+	 * Removes everything kept about the subscriber: their keys, their sign
+	 * in identities, their role, their spot on the boards and in other
+	 * subscribers' friends lists. Open trades, listings, the queue they
+	 * host and the data export archive go first, and they leave the queues
+	 * they joined so the next visitor gets their turn.
+	 * The id is tombstoned so it's never reused.
+	 */
+	pub fn delete(redis: &DbConn, subscriber: &Subscriber) -> Result<(), AccountError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(AccountError::new("No Id"))
+		};
+		if let Err(e) = Trade::abandon(redis, &subscriber_id) {
+			return Err(AccountError::new(&e.details))
+		}
+		let listings = match Listing::list_owned(redis, &subscriber_id) {
+			Ok(listings) => listings,
+			Err(e) => return Err(AccountError::new(&e.details))
+		};
+		for listing in listings {
+			if let Err(e) = Listing::delete(redis, &subscriber_id, &listing.id.unwrap_or_default()) {
+				return Err(AccountError::new(&e.details))
+			}
+		}
+		if let Ok(session) = Session::hosted(redis, &subscriber_id) {
+			if let Err(e) = Session::close(redis, &subscriber_id, &session.id.unwrap_or_default()) {
+				return Err(AccountError::new(&e.details))
+			}
+		}
+		let joined = match Session::joined(redis, &subscriber_id) {
+			Ok(joined) => joined,
+			Err(e) => return Err(AccountError::new(&e.details))
+		};
+		for id in joined {
+			if let Err(e) = Session::leave(redis, &subscriber_id, &id) {
+				return Err(AccountError::new(&e.details))
+			}
+		}
+		let classes = match Class::list_classes(redis, &subscriber_id) {
+			Ok(classes) => classes,
+			Err(e) => return Err(AccountError::new(&e.details))
+		};
+		if let Err(e) = DataExport::remove(redis, &subscriber_id) {
+			return Err(AccountError::new(&e.details))
+		}
+		let identities = Account::identities(redis, &subscriber_id)?;
+		let keys = Account::keys(redis, &subscriber_id)?;
+		// Nobody keeps a reverse index of friends, every list is checked
+		let friend_lists: Vec<String> = match redis.scan_match::<_, String>("*:friends") {
+			Ok(lists) => lists.collect(),
+			Err(_e) => return Err(AccountError::new("Friends not found"))
+		};
+		let mut query = pipe();
+		query.atomic();
+		for key in &keys {
+			query.del(key).ignore();
+		}
+		for identity in &identities {
+			query.hdel("tokenmap", identity).ignore();
+		}
+		for list in &friend_lists {
+			query.hdel(list, &subscriber_id).ignore();
+		}
+		for class in classes {
+			query.zrem(Leaderboard::key(&Some(class)), &subscriber_id).ignore();
+		}
+		match query
+				.zrem(Leaderboard::key(&None), &subscriber_id)
+				.ignore()
+				.srem(Leaderboard::optout_key(), &subscriber_id)
+				.ignore()
+				.srem(Leaderboard::counted_key(), &subscriber_id)
+				.ignore()
+				.srem(Class::indexed_key(), &subscriber_id)
+				.ignore()
+				.hdel(Admin::roles_key(), &subscriber_id)
+				.ignore()
+				.hdel(Admin::suspended_key(), &subscriber_id)
+				.ignore()
+				.zadd(Account::tombstones_key(), &subscriber_id, now())
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(AccountError::new("unknown error"))
+		}
+	}
+}
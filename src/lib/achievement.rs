@@ -0,0 +1,194 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use crate::lib::catalog::{Catalog, AchievementRule};
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::recipe::RecipeCard;
+use crate::lib::trade::now;
+
+// What profiles show, the names live in the catalog
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Badge {
+	pub id: String,
+	pub unlocked: u64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Achievement {
+	pub id: String,
+	pub name: String,
+	pub description: String,
+	pub progress: u32,
+	pub goal: u32,
+	pub unlocked: Option<u64>,
+}
+
+#[derive(Debug)]
+pub struct AchievementError {
+	pub details: String
+}
+
+impl AchievementError {
+	fn new(msg: &str) -> AchievementError {
+		AchievementError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for AchievementError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for AchievementError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+/*
+ * Everything the rules are checked against
+ */
+struct Metrics {
+	donated: u32,
+	classes: HashMap<String, u32>,
+	trades: u32,
+	friends: u32,
+	recipes: u32,
+	profile: bool,
+}
+
+impl Metrics {
+
+	fn load(redis: &DbConn, subscriber_id: &String, catalog: &Catalog) -> Result<Metrics, AchievementError> {
+		if let Err(e) = Leaderboard::ensure_counted(redis, subscriber_id) {
+			return Err(AchievementError::new(&e.details))
+		}
+		let mut classes: Vec<String> = catalog.achievements.iter()
+			.filter_map(|rule| rule.class.clone())
+			.collect();
+		classes.sort();
+		classes.dedup();
+		let (donated, trades, friends, name) = match pipe()
+				.zscore(Leaderboard::key(&None), subscriber_id)
+				.zcard([subscriber_id.to_owned(), ":trades:history".to_string()].concat())
+				.hlen([subscriber_id.to_owned(), ":friends".to_string()].concat())
+				.hget([subscriber_id.to_owned(), ":profile".to_string()].concat(), "name")
+				.query::<(Option<i64>, u32, u32, Option<String>)>(&**redis) {
+			Ok(value) => value,
+			Err(_e) => return Err(AchievementError::new("unknown error"))
+		};
+		let mut query = pipe();
+		for class in &classes {
+			query.zscore(Leaderboard::key(&Some(class.to_owned())), subscriber_id);
+		}
+		let scores = if classes.is_empty() {
+			Vec::new()
+		} else {
+			match query.query::<Vec<Option<i64>>>(&**redis) {
+				Ok(scores) => scores,
+				Err(_e) => return Err(AchievementError::new("unknown error"))
+			}
+		};
+		let recipes = match RecipeCard::get_all(redis, subscriber_id) {
+			Ok(cards) => cards.iter().filter(|card| card.learned).count() as u32,
+			Err(e) => return Err(AchievementError::new(&e.details))
+		};
+		let score = |value: Option<i64>| value.unwrap_or(0).max(0) as u32;
+		Ok(Metrics {
+			donated: score(donated),
+			classes: classes.into_iter().zip(scores).map(|(class, value)| (class, score(value))).collect(),
+			trades: trades,
+			friends: friends,
+			recipes: recipes,
+			profile: name.map_or(false, |name| !name.is_empty()),
+		})
+	}
+
+	/*
+	 * Where the subscriber is on a rule and where it ends
+	 */
+	fn progress(&self, rule: &AchievementRule, catalog: &Catalog) -> (u32, u32) {
+		let goal = rule.count.unwrap_or(1);
+		match rule.kind.as_str() {
+			"donated" => (self.donated, goal),
+			"class_donated" => {
+				let class = rule.class.clone().unwrap_or_default();
+				(self.classes.get(&class).cloned().unwrap_or(0), goal)
+			},
+			"trades" => (self.trades, goal),
+			"friends" => (self.friends, goal),
+			"recipes" => (self.recipes, rule.count.unwrap_or(catalog.recipes.len() as u32)),
+			"profile" => (if self.profile { 1 } else { 0 }, 1),
+			// Rules we don't know about can't be unlocked
+			_ => (0, goal.max(1))
+		}
+	}
+}
+
+impl Achievement {
+
+	fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":achievements".to_string()].concat()
+	}
+
+	/*
+	 * Badges the subscriber has unlocked, oldest first
+	 */
+	pub fn badges(redis: &DbConn, subscriber_id: &String) -> Result<Vec<Badge>, AchievementError> {
+		match redis.hgetall::<_, Vec<(String, u64)>>(Achievement::key(subscriber_id)) {
+			Ok(value) => {
+				let mut badges: Vec<Badge> = value.into_iter()
+					.map(|(id, unlocked)| Badge { id: id, unlocked: unlocked })
+					.collect();
+				badges.sort_by(|a, b| a.unlocked.cmp(&b.unlocked));
+				Ok(badges)
+			},
+			Err(_e) => Err(AchievementError::new("Achievements not found"))
+		}
+	}
+
+	/*
+	 * Checks every rule and unlocks the ones reached, unlocked
+	 * achievements stay unlocked. Returns the new ones.
+	 */
+	pub fn evaluate(redis: &DbConn, subscriber_id: &String, catalog: &Catalog) -> Result<Vec<Achievement>, AchievementError> {
+		let mut unlocked: Vec<Achievement> = Vec::new();
+		for achievement in Achievement::get_all(redis, subscriber_id, catalog)? {
+			if achievement.unlocked.is_none() && achievement.progress >= achievement.goal {
+				let timestamp = now();
+				match redis.hset_nx::<_,_,_,bool>(Achievement::key(subscriber_id), &achievement.id, timestamp) {
+					Ok(true) => unlocked.push(Achievement { unlocked: Some(timestamp), ..achievement }),
+					Ok(false) => {},
+					Err(_e) => return Err(AchievementError::new("unknown error"))
+				}
+			}
+		}
+		Ok(unlocked)
+	}
+
+	/*
+	 * Every achievement with the subscriber's progress
+	 */
+	pub fn get_all(redis: &DbConn, subscriber_id: &String, catalog: &Catalog) -> Result<Vec<Achievement>, AchievementError> {
+		let metrics = Metrics::load(redis, subscriber_id, catalog)?;
+		let badges = Achievement::badges(redis, subscriber_id)?;
+		Ok(catalog.achievements.iter()
+			.map(|rule| {
+				let (progress, goal) = metrics.progress(rule, catalog);
+				Achievement {
+					id: rule.id.to_owned(),
+					name: rule.name.to_owned(),
+					description: rule.description.to_owned(),
+					progress: progress.min(goal),
+					goal: goal,
+					unlocked: badges.iter().find(|badge| badge.id == rule.id).map(|badge| badge.unlocked)
+				}
+			})
+			.collect())
+	}
+}
@@ -0,0 +1,670 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{self, Commands, pipe, Pipeline, PipelineCommands, Value};
+use rocket_contrib::json::JsonValue;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::error;
+use std::fmt;
+
+use crate::lib::account::Account;
+use crate::lib::archive::DataExport;
+use crate::lib::class::{Class, Item, RESERVED};
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::recipe::RecipeCard;
+use crate::lib::subscriber::{Subscriber, Friend};
+use crate::lib::trade::now;
+use crate::lib::wishlist::Wishlist;
+
+const PAGE_SIZE: isize = 50;
+// Subscribers read at a time while searching
+const CHUNK: isize = 500;
+// The audit stream is trimmed around this size, older entries go first
+const MAX_AUDIT_ENTRIES: usize = 10000;
+const DEFAULT_AUDIT_COUNT: usize = 100;
+
+/*
+ * From least to most trusted, every role can do what the ones before it can
+ */
+pub const ROLES: &[&str] = &["user", "moderator", "admin"];
+
+// Keys besides the class hashes a merge takes care of
+const MERGED: &[&str] = &["profile", "friends", "classes", "revisions", "recipes", "wishlist", "data-export"];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SubscriberSummary {
+	pub id: String,
+	pub name: String,
+	pub donated: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Repair {
+	pub subscriber: String,
+	pub class: String,
+	pub item: String,
+	pub old: String,
+	// Missing when the value couldn't be made sense of and was dropped
+	pub new: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct MergeReport {
+	pub items: u32,
+	pub friends: u32,
+	pub identities: u32,
+	pub recipes: u32,
+	pub wishlist: u32,
+	// Keys of the duplicate that had nowhere to go and were deleted with it
+	pub discarded: Vec<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Stats {
+	pub keys: u64,
+	pub subscribers: u64,
+	pub tombstones: u64,
+	// Keys by what they hold, "class" for the inventory hashes
+	pub kinds: BTreeMap<String, u64>,
+	pub memory: String,
+}
+
+/*
+ * What moderators get to see about a subscriber, without their inventory
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SubscriberLookup {
+	pub id: String,
+	pub name: String,
+	pub role: String,
+	// The reason, missing when the subscriber isn't suspended
+	pub suspended: Option<String>,
+	pub identities: Vec<String>,
+	pub donated: i64,
+	pub classes: Vec<String>,
+	pub friends: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Suspension {
+	pub reason: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RoleChange {
+	pub role: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+	// Stream entry id
+	pub id: String,
+	// Subscriber id of whoever did it, or the admin tool
+	pub actor: String,
+	pub action: String,
+	// The subscriber it was done to, empty for things like catalog reloads
+	pub target: String,
+	pub details: String,
+	pub timestamp: u64,
+}
+
+pub struct Admin;
+
+#[derive(Debug)]
+pub struct AdminError {
+	pub details: String
+}
+
+impl AdminError {
+	fn new(msg: &str) -> AdminError {
+		AdminError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for AdminError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for AdminError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+/*
+ * Makes sense of class values written by old or buggy clients,
+ * None if there's nothing to save
+ */
+pub fn repair_meta(meta: &str) -> Option<String> {
+	let parts: Vec<String> = meta.split(':').map(|part| part.trim().to_lowercase()).collect();
+	let donated = |value: &str| match value {
+		"true" | "1" | "yes" => Some(true),
+		"false" | "0" | "no" | "" => Some(false),
+		_ => None
+	};
+	let (donated, stock) = match parts.len() {
+		// Only one of the two was written
+		1 => match (donated(&parts[0]), parts[0].parse::<i64>()) {
+			(_, Ok(stock)) if parts[0] != "0" && parts[0] != "1" => (false, stock),
+			(Some(donated), _) => (donated, 0),
+			_ => return None
+		},
+		2 => (donated(&parts[0])?, if parts[1].is_empty() { 0 } else { parts[1].parse::<i64>().ok()? }),
+		_ => return None
+	};
+	Some([donated.to_string(), ":".to_string(), stock.max(0).min(u32::max_value() as i64).to_string()].concat())
+}
+
+fn parse_audit_entry(value: &Value) -> Option<AuditEntry> {
+	let parts: Vec<Value> = redis::from_redis_value(value).ok()?;
+	if parts.len() != 2 {
+		return None
+	}
+	let id: String = redis::from_redis_value(&parts[0]).ok()?;
+	let fields: Vec<(String, String)> = redis::from_redis_value(&parts[1]).ok()?;
+	let field = |name: &str| fields.iter()
+		.find(|(key, _)| key == name)
+		.map(|(_, value)| value.to_owned())
+		.unwrap_or_default();
+	Some(AuditEntry {
+		id: id,
+		actor: field("actor"),
+		action: field("action"),
+		target: field("target"),
+		details: field("details"),
+		timestamp: field("timestamp").parse().unwrap_or(0),
+	})
+}
+
+fn subscriber_exists(redis: &DbConn, id: &String) -> Result<bool, AdminError> {
+	match redis.zscore::<_, _, Option<f64>>(Leaderboard::key(&None), id) {
+		Ok(score) => Ok(score.is_some()),
+		Err(_e) => Err(AdminError::new("unknown error"))
+	}
+}
+
+/*
+ * The subscriber's keys a merge has nowhere to put, without the id in front.
+ * Items, recipes, the wishlist and friends move over, the profile,
+ * revisions and data export are the account's own and go with it.
+ */
+fn unmerged_keys(redis: &DbConn, id: &String) -> Result<Vec<String>, AdminError> {
+	let classes = Class::list_classes(redis, id).map_err(|e| AdminError::new(&e.details))?;
+	let keys = Account::keys(redis, id).map_err(|e| AdminError::new(&e.details))?;
+	let prefix = [id.to_owned(), ":".to_string()].concat();
+	let mut unmerged: Vec<String> = keys.iter()
+		.map(|key| key.trim_start_matches(prefix.as_str()).to_string())
+		.filter(|key| !MERGED.contains(&key.as_str()) && !classes.contains(key))
+		.collect();
+	unmerged.sort();
+	Ok(unmerged)
+}
+
+impl Admin {
+
+	pub(crate) fn roles_key() -> &'static str {
+		"roles"
+	}
+
+	pub(crate) fn suspended_key() -> &'static str {
+		"suspended"
+	}
+
+	fn audit_key() -> &'static str {
+		"audit"
+	}
+
+	/*
+	 * Whether a role is trusted with what the needed one can do
+	 */
+	pub fn has_role(role: &String, needed: &str) -> bool {
+		let rank = |role: &str| ROLES.iter().position(|known| *known == role);
+		match (rank(role), rank(needed)) {
+			(Some(role), Some(needed)) => role >= needed,
+			_ => false
+		}
+	}
+
+	/*
+	 * The subscriber's role, only the ones above "user" are stored
+	 */
+	pub fn role(redis: &DbConn, subscriber_id: &String) -> Result<String, AdminError> {
+		match redis.hget::<_, _, Option<String>>(Admin::roles_key(), subscriber_id) {
+			Ok(role) => Ok(role.unwrap_or(ROLES[0].to_string())),
+			Err(_e) => Err(AdminError::new("Role not found"))
+		}
+	}
+
+	pub fn set_role(redis: &DbConn, actor: &String, subscriber_id: &String, role: &String) -> Result<(), AdminError> {
+		if !ROLES.contains(&role.as_str()) {
+			return Err(AdminError::new("Invalid role"))
+		}
+		if !subscriber_exists(redis, subscriber_id)? {
+			return Err(AdminError::new("Subscriber not found"))
+		}
+		let mut query = pipe();
+		query.atomic();
+		if role == ROLES[0] {
+			query.hdel(Admin::roles_key(), subscriber_id).ignore();
+		} else {
+			query.hset(Admin::roles_key(), subscriber_id, role).ignore();
+		}
+		Admin::record(&mut query, actor, "role", subscriber_id, role);
+		match query.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(AdminError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Why the subscriber was suspended, None if they aren't
+	 */
+	pub fn suspension(redis: &DbConn, subscriber_id: &String) -> Result<Option<String>, AdminError> {
+		match redis.hget::<_, _, Option<String>>(Admin::suspended_key(), subscriber_id) {
+			Ok(reason) => Ok(reason),
+			Err(_e) => Err(AdminError::new("Suspension not found"))
+		}
+	}
+
+	/*
+	 * Keeps the subscriber from signing in, their data stays as it is
+	 */
+	pub fn suspend(redis: &DbConn, actor: &String, subscriber_id: &String, reason: &String) -> Result<(), AdminError> {
+		if reason.trim().is_empty() {
+			return Err(AdminError::new("A reason is needed"))
+		}
+		if !subscriber_exists(redis, subscriber_id)? {
+			return Err(AdminError::new("Subscriber not found"))
+		}
+		let mut query = pipe();
+		query.atomic()
+			.hset(Admin::suspended_key(), subscriber_id, reason.trim())
+			.ignore();
+		Admin::record(&mut query, actor, "suspend", subscriber_id, reason.trim());
+		match query.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(AdminError::new("unknown error"))
+		}
+	}
+
+	pub fn unsuspend(redis: &DbConn, actor: &String, subscriber_id: &String) -> Result<(), AdminError> {
+		if Admin::suspension(redis, subscriber_id)?.is_none() {
+			return Err(AdminError::new("Subscriber isn't suspended"))
+		}
+		let mut query = pipe();
+		query.atomic()
+			.hdel(Admin::suspended_key(), subscriber_id)
+			.ignore();
+		Admin::record(&mut query, actor, "unsuspend", subscriber_id, "");
+		match query.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(AdminError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Replaces a profile name with the subscriber id, in the profile
+	 * and in every friends list that has it
+	 */
+	pub fn reset_name(redis: &DbConn, actor: &String, subscriber_id: &String) -> Result<String, AdminError> {
+		let mut subscriber = Subscriber { id: Some(subscriber_id.to_owned()), social_id: None, proto: None };
+		let old = match subscriber.get_profile(redis) {
+			Ok(profile) => profile.name,
+			Err(e) => return Err(AdminError::new(&e.details))
+		};
+		let lists: Vec<String> = match redis.scan_match::<_, String>("*:friends") {
+			Ok(lists) => lists.collect(),
+			Err(_e) => return Err(AdminError::new("Friends not found"))
+		};
+		let mut query = pipe();
+		query.atomic()
+			.hset([subscriber_id.to_owned(), ":profile".to_string()].concat(), "name", subscriber_id)
+			.ignore();
+		for list in &lists {
+			match redis.hexists::<_, _, bool>(list, subscriber_id) {
+				Ok(true) => { query.hset(list, subscriber_id, subscriber_id).ignore(); },
+				Ok(false) => {},
+				Err(_e) => return Err(AdminError::new("Friends not found"))
+			}
+		}
+		Admin::record(&mut query, actor, "reset-name", subscriber_id, &old);
+		match query.query::<()>(&**redis) {
+			Ok(_) => Ok(subscriber_id.to_owned()),
+			Err(_e) => Err(AdminError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Who the subscriber is, what they can do and whether they're suspended
+	 */
+	pub fn lookup(redis: &DbConn, subscriber_id: &String) -> Result<SubscriberLookup, AdminError> {
+		let donated = match redis.zscore::<_, _, Option<i64>>(Leaderboard::key(&None), subscriber_id) {
+			Ok(Some(donated)) => donated,
+			Ok(None) => return Err(AdminError::new("Subscriber not found")),
+			Err(_e) => return Err(AdminError::new("unknown error"))
+		};
+		let mut subscriber = Subscriber { id: Some(subscriber_id.to_owned()), social_id: None, proto: None };
+		Ok(SubscriberLookup {
+			id: subscriber_id.to_owned(),
+			name: subscriber.get_profile(redis).map(|profile| profile.name).unwrap_or_default(),
+			role: Admin::role(redis, subscriber_id)?,
+			suspended: Admin::suspension(redis, subscriber_id)?,
+			identities: Account::identities(redis, subscriber_id).map_err(|e| AdminError::new(&e.details))?,
+			donated: donated,
+			classes: Class::list_classes(redis, subscriber_id).map_err(|e| AdminError::new(&e.details))?,
+			friends: subscriber.get_friends(redis).map(|friends| friends.len()).unwrap_or(0),
+		})
+	}
+
+	/*
+	 * Queues an audit entry so it's written along with the action itself
+	 */
+	pub(crate) fn record(query: &mut Pipeline, actor: &String, action: &str, target: &String, details: &str) {
+		query.cmd("XADD")
+			.arg(Admin::audit_key())
+			.arg("MAXLEN").arg("~").arg(MAX_AUDIT_ENTRIES)
+			.arg("*")
+			.arg("actor").arg(actor)
+			.arg("action").arg(action)
+			.arg("target").arg(target)
+			.arg("details").arg(details)
+			.arg("timestamp").arg(now())
+			.ignore();
+	}
+
+	/*
+	 * Writes an audit entry for actions that don't touch redis themselves
+	 */
+	pub fn audit(redis: &DbConn, actor: &String, action: &str, target: &String, details: &str) -> Result<(), AdminError> {
+		let mut query = pipe();
+		Admin::record(&mut query, actor, action, target, details);
+		match query.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(AdminError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * The latest audit entries, newest first
+	 */
+	pub fn audit_log(redis: &DbConn, count: Option<usize>) -> Result<Vec<AuditEntry>, AdminError> {
+		let count = count.unwrap_or(DEFAULT_AUDIT_COUNT).min(MAX_AUDIT_ENTRIES);
+		match redis::cmd("XREVRANGE")
+				.arg(Admin::audit_key())
+				.arg("+")
+				.arg("-")
+				.arg("COUNT").arg(count)
+				.query::<Vec<Value>>(&**redis) {
+			Ok(values) => Ok(values.iter().filter_map(parse_audit_entry).collect()),
+			Err(_e) => Err(AdminError::new("Audit log not found"))
+		}
+	}
+
+	fn summaries(redis: &DbConn, ranked: Vec<(String, i64)>) -> Result<Vec<SubscriberSummary>, AdminError> {
+		if ranked.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for (id, _) in &ranked {
+			query.hget([id.to_owned(), ":profile".to_string()].concat(), "name");
+		}
+		match query.query::<Vec<Option<String>>>(&**redis) {
+			Ok(names) => Ok(ranked.into_iter()
+								.zip(names)
+								.map(|((id, donated), name)| SubscriberSummary {
+																id: id,
+																name: name.unwrap_or_default(),
+																donated: donated
+															})
+								.collect()),
+			Err(_e) => Err(AdminError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * A page of subscribers, the ones with most donations first
+	 */
+	pub fn list(redis: &DbConn, page: usize) -> Result<Vec<SubscriberSummary>, AdminError> {
+		let start = page as isize * PAGE_SIZE;
+		match redis.zrevrange_withscores::<_, Vec<(String, i64)>>(Leaderboard::key(&None), start, start + PAGE_SIZE - 1) {
+			Ok(ranked) => Admin::summaries(redis, ranked),
+			Err(_e) => Err(AdminError::new("Subscribers not found"))
+		}
+	}
+
+	/*
+	 * Subscribers whose id or profile name has the text, ignoring case
+	 */
+	pub fn search(redis: &DbConn, text: &String) -> Result<Vec<SubscriberSummary>, AdminError> {
+		let text = text.to_lowercase();
+		let mut found: Vec<SubscriberSummary> = Vec::new();
+		let mut start: isize = 0;
+		loop {
+			let chunk = match redis.zrevrange_withscores::<_, Vec<(String, i64)>>(Leaderboard::key(&None), start, start + CHUNK - 1) {
+				Ok(chunk) => chunk,
+				Err(_e) => return Err(AdminError::new("Subscribers not found"))
+			};
+			let full = chunk.len() as isize == CHUNK;
+			found.extend(Admin::summaries(redis, chunk)?.into_iter()
+				.filter(|summary| summary.id.to_lowercase().contains(&text) || summary.name.to_lowercase().contains(&text)));
+			if !full {
+				return Ok(found)
+			}
+			start += CHUNK;
+		}
+	}
+
+	/*
+	 * Everything kept about a subscriber
+	 */
+	pub fn show(redis: &DbConn, id: &String) -> Result<JsonValue, AdminError> {
+		if !subscriber_exists(redis, id)? {
+			return Err(AdminError::new("Subscriber not found"))
+		}
+		match DataExport::collect(redis, id) {
+			Ok(files) => {
+				let data: serde_json::Map<String, serde_json::Value> = files.into_iter()
+					.map(|(name, value)| (name.to_string(), value.into_inner()))
+					.collect();
+				Ok(json!(data))
+			},
+			Err(e) => Err(AdminError::new(&e.details))
+		}
+	}
+
+	pub fn delete(redis: &DbConn, id: &String) -> Result<(), AdminError> {
+		if !subscriber_exists(redis, id)? {
+			return Err(AdminError::new("Subscriber not found"))
+		}
+		let subscriber = Subscriber { id: Some(id.to_owned()), social_id: None, proto: None };
+		Account::delete(redis, &subscriber).map_err(|e| AdminError::new(&e.details))
+	}
+
+	/*
+	 * Folds a duplicate account into another one: items and recipes are
+	 * added up, the wishlists joined, friends and sign in identities move
+	 * over, then the duplicate is deleted. Whoever had the duplicate as a
+	 * friend gets the other one. Anything else the duplicate has, like
+	 * turnip weeks or trades, would be lost so it's refused unless forced.
+	 */
+	pub fn merge(redis: &DbConn, from: &String, into: &String, force: bool) -> Result<MergeReport, AdminError> {
+		if from == into {
+			return Err(AdminError::new("Can't merge a subscriber into itself"))
+		}
+		if !subscriber_exists(redis, from)? || !subscriber_exists(redis, into)? {
+			return Err(AdminError::new("Subscriber not found"))
+		}
+		let discarded = unmerged_keys(redis, from)?;
+		if !discarded.is_empty() && !force {
+			return Err(AdminError::new(&[
+				"Merging would lose ".to_string(),
+				discarded.join(", "),
+				", run it again with --force to drop them".to_string()
+			].concat()))
+		}
+		let mut report = MergeReport { items: 0, friends: 0, identities: 0, recipes: 0, wishlist: 0, discarded: discarded };
+		let source = Class::get_all(redis, from).map_err(|e| AdminError::new(&e.details))?;
+		let target = Class::get_all(redis, into).map_err(|e| AdminError::new(&e.details))?;
+		let client = ["admin:merge:".to_string(), from.to_owned()].concat();
+		for (name, items) in source {
+			let existing: HashMap<String, Item> = target.get(&name)
+				.map(|items| items.iter().map(|item| (item.id.to_owned(), item.clone())).collect())
+				.unwrap_or_default();
+			let merged: Vec<Item> = items.into_iter()
+				.map(|item| match existing.get(&item.id) {
+					Some(old) => Item {
+						donated: old.donated || item.donated,
+						stock: old.stock.saturating_add(item.stock),
+						..item
+					},
+					None => item
+				})
+				.collect();
+			report.items += merged.len() as u32;
+			if let Err(e) = Class(name).set_class(redis, into, merged, &client, None) {
+				return Err(AdminError::new(&e.details))
+			}
+		}
+		report.recipes = RecipeCard::merge(redis, from, into).map_err(|e| AdminError::new(&e.details))?;
+		report.wishlist = Wishlist::merge(redis, from, into).map_err(|e| AdminError::new(&e.details))?;
+		let mut source = Subscriber { id: Some(from.to_owned()), social_id: None, proto: None };
+		let mut target = Subscriber { id: Some(into.to_owned()), social_id: None, proto: None };
+		for friend in source.get_friends(redis).map_err(|e| AdminError::new(&e.details))? {
+			if &friend.id == into {
+				continue;
+			}
+			if target.set_friend(redis, friend, None).is_ok() {
+				report.friends += 1;
+			}
+		}
+		let name = target.get_profile(redis).map(|profile| profile.name).unwrap_or_default();
+		let lists: Vec<String> = match redis.scan_match::<_, String>("*:friends") {
+			Ok(lists) => lists.collect(),
+			Err(_e) => return Err(AdminError::new("Friends not found"))
+		};
+		let mut query = pipe();
+		query.atomic();
+		for list in &lists {
+			if list == &[into.to_owned(), ":friends".to_string()].concat() {
+				continue;
+			}
+			match redis.hexists::<_, _, bool>(list, from) {
+				Ok(true) => {
+					let friend = Friend { id: into.to_owned(), name: if name.is_empty() { into.to_owned() } else { name.to_owned() } };
+					query.hset_nx(list, &friend.id, &friend.name).ignore();
+				},
+				Ok(false) => {},
+				Err(_e) => return Err(AdminError::new("Friends not found"))
+			}
+		}
+		let identities = Account::identities(redis, from).map_err(|e| AdminError::new(&e.details))?;
+		for identity in &identities {
+			query.hset("tokenmap", identity, into).ignore();
+		}
+		report.identities = identities.len() as u32;
+		if query.query::<()>(&**redis).is_err() {
+			return Err(AdminError::new("unknown error"))
+		}
+		Admin::delete(redis, from)?;
+		Ok(report)
+	}
+
+	/*
+	 * Finds class values that aren't "donated:stock" and fixes them,
+	 * for one subscriber or all of them. A dry run only reports.
+	 */
+	pub fn repair(redis: &DbConn, subscriber_id: Option<&String>, dry_run: bool) -> Result<Vec<Repair>, AdminError> {
+		let ids: Vec<String> = match subscriber_id {
+			Some(id) => vec![id.to_owned()],
+			None => match redis.zrange::<_, Vec<String>>(Leaderboard::key(&None), 0, -1) {
+				Ok(ids) => ids,
+				Err(_e) => return Err(AdminError::new("Subscribers not found"))
+			}
+		};
+		let mut repairs: Vec<Repair> = Vec::new();
+		for id in ids {
+			let classes = Class::list_classes(redis, &id).map_err(|e| AdminError::new(&e.details))?;
+			let mut found: Vec<Repair> = Vec::new();
+			for class in classes {
+				let key = [id.to_owned(), ":".to_string(), class.to_owned()].concat();
+				let values = match redis.hgetall::<_, Vec<(String, String)>>(&key) {
+					Ok(values) => values,
+					Err(_e) => return Err(AdminError::new("Items not found"))
+				};
+				for (item, meta) in values {
+					if Item::from_meta(&class, &item, &meta).is_none() {
+						found.push(Repair {
+							subscriber: id.to_owned(),
+							class: class.to_owned(),
+							item: item,
+							new: repair_meta(&meta),
+							old: meta
+						});
+					}
+				}
+			}
+			if !dry_run && !found.is_empty() {
+				let client = "admin:repair".to_string();
+				let mut by_class: BTreeMap<String, Vec<(String, String, Option<String>)>> = BTreeMap::new();
+				for repair in &found {
+					by_class.entry(repair.class.to_owned()).or_insert(Vec::new())
+						.push((repair.item.to_owned(), repair.old.to_owned(), repair.new.clone()));
+				}
+				let mut written: Vec<(String, String)> = Vec::new();
+				for (class, fixes) in by_class {
+					let items = Class(class.to_owned()).repair(redis, &id, &fixes, &client)
+						.map_err(|e| AdminError::new(&e.details))?;
+					written.extend(items.into_iter().map(|item| (class.to_owned(), item)));
+				}
+				// Values a client rewrote since they were read are theirs now
+				found.retain(|repair| written.contains(&(repair.class.to_owned(), repair.item.to_owned())));
+			}
+			repairs.extend(found);
+		}
+		Ok(repairs)
+	}
+
+	/*
+	 * How many keys there are of each kind and how much memory they take
+	 */
+	pub fn stats(redis: &DbConn) -> Result<Stats, AdminError> {
+		let (keys, subscribers, tombstones, info) = match pipe()
+				.cmd("DBSIZE")
+				.zcard(Leaderboard::key(&None))
+				.zcard(Account::tombstones_key())
+				.cmd("INFO").arg("memory")
+				.query::<(u64, u64, u64, String)>(&**redis) {
+			Ok(value) => value,
+			Err(_e) => return Err(AdminError::new("unknown error"))
+		};
+		let mut kinds: BTreeMap<String, u64> = BTreeMap::new();
+		let all = match redis.scan::<String>() {
+			Ok(all) => all.collect::<Vec<String>>(),
+			Err(_e) => return Err(AdminError::new("unknown error"))
+		};
+		for key in all {
+			let parts: Vec<&str> = key.split(':').collect();
+			let kind = match parts.len() {
+				// Global keys like "tokenmap" or "subscribers"
+				1 => key.to_owned(),
+				_ if parts[0] == "leaderboard" || parts[0] == "listings" => [parts[0], ":*"].concat(),
+				_ if parts[0] == "listing" || parts[0] == "trade" || parts[0] == "queue" => parts[0].to_string(),
+				2 if RESERVED.contains(&parts[1]) => parts[1].to_string(),
+				2 => "class".to_string(),
+				_ => parts[1].to_string()
+			};
+			*kinds.entry(kind).or_insert(0) += 1;
+		}
+		let memory = info.lines()
+			.find(|line| line.starts_with("used_memory_human:"))
+			.map_or(String::new(), |line| line["used_memory_human:".len()..].trim().to_string());
+		Ok(Stats {
+			keys: keys,
+			subscribers: subscribers,
+			tombstones: tombstones,
+			kinds: kinds,
+			memory: memory
+		})
+	}
+}
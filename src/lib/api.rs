@@ -1,18 +1,46 @@
 use rocket::http::{ContentType, Status};
-use rocket::request::{self, Request, FromRequest};
+use rocket::request::{self, Request, FromRequest, LenientForm};
 use rocket::response;
 use rocket::response::{Responder, Response};
+use rocket::response::content::Content;
+use rocket::Data;
 use rocket_contrib::json::{Json, JsonValue};
 use rocket::Outcome;
+use rocket::State;
 use google_signin;
 use std::env;
+use std::io::Read;
+use std::fs::File;
+use chrono::Utc;
 
 use crate::lib::subscriber::Subscriber;
 use crate::lib::subscriber::Friend;
-use crate::lib::subscriber::Profile;
+use crate::lib::subscriber::{Profile, SubscriberError};
 use crate::lib::class::Class;
 use crate::lib::class::Item;
-use crate::DbConn;
+use crate::lib::trade::TradeSuggestion;
+use crate::lib::trade::Trade;
+use crate::lib::wishlist::{Wishlist, WishlistItem};
+use crate::lib::listing::{Listing, ListingQuery};
+use crate::lib::turnip::{self, TurnipWeek, TurnipPrice, Gates, BoardEntry};
+use crate::lib::predictor::TurnipPrediction;
+use crate::lib::queue::Session;
+use crate::lib::catalog::LiveCatalog;
+use crate::lib::villager::{Resident, Dreamies};
+use crate::lib::recipe::RecipeCard;
+use crate::lib::event::{EventDates, EventQuery};
+use crate::lib::checklist::{Checklist, Task};
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::achievement::Achievement;
+use crate::lib::history::{History, HistoryQuery};
+use crate::lib::snapshot::Snapshot;
+use crate::lib::sync::{self, Revision, SyncRequest};
+use crate::lib::etag;
+use crate::lib::transfer::{self, Export};
+use crate::lib::account::{Account, Reauth};
+use crate::lib::archive::DataExport;
+use crate::lib::admin::{Admin, RoleChange, Suspension};
+use crate::{DbConn, DbConnPool};
 
 #[derive(Debug)]
 pub struct ApiResponse {
@@ -23,6 +51,9 @@ pub struct ApiResponse {
 #[derive(Debug)]
 pub struct Bearer (String); 
 
+// Largest import body read, in bytes
+const IMPORT_LIMIT: u64 = 5 * 1024 * 1024;
+
 /// Returns true if `key` is a valid API key string.
 fn is_valid(_key: &str) -> bool {
 	true
@@ -51,6 +82,151 @@ impl<'a, 'r> FromRequest<'a, 'r> for Bearer {
     }
 }
 
+/// Optional X-Client-Id header, tells apart the devices syncing an inventory
+#[derive(Debug)]
+pub struct ClientId (String);
+
+impl<'a, 'r> FromRequest<'a, 'r> for ClientId {
+    type Error = JsonValue;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        match request.headers().get_one("X-Client-Id") {
+            Some(client) => Outcome::Success(ClientId(client.chars().take(64).collect())),
+            None => Outcome::Success(ClientId("unknown".to_string())),
+        }
+    }
+}
+
+/// If-None-Match and If-Match headers, the ETags the client has
+#[derive(Debug)]
+pub struct IfNoneMatch (Option<String>);
+
+#[derive(Debug)]
+pub struct IfMatch (Option<String>);
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfNoneMatch {
+    type Error = JsonValue;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(IfNoneMatch(request.headers().get_one("If-None-Match").map(|tags| tags.to_string())))
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for IfMatch {
+    type Error = JsonValue;
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, Self::Error> {
+        Outcome::Success(IfMatch(request.headers().get_one("If-Match").map(|tags| tags.to_string())))
+    }
+}
+
+/// The 412 for writes whose If-Match ETag isn't the current one
+fn precondition_failed() -> ApiResponse {
+	ApiResponse {
+		json: json!({"message": etag::CHANGED}),
+		status: Status::PreconditionFailed,
+	}
+}
+
+/// A 403 for suspended subscribers, a 404 for the ones that never registered
+fn not_signed_in(e: SubscriberError) -> ApiResponse {
+	if e.is_suspended() {
+		return ApiResponse {
+			json: json!({"message": "Subscriber suspended"}),
+			status: Status::Forbidden,
+		}
+	}
+	ApiResponse {
+		json: json!({"message":"Subscriber not found"}),
+		status: Status::NotFound,
+	}
+}
+
+/// Signs the caller in for the /admin routes, their id if their role is at least the needed one
+fn staff(conn: &DbConn, key: Result<Bearer, JsonValue>, needed: &str) -> Result<String, ApiResponse> {
+	let bearer = match key {
+		Ok(bearer) => bearer,
+		Err(json_error) => return Err(ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		})
+	};
+	let mut client = google_signin::Client::new();
+	client.audiences.push(env::var("GGL_KEY").unwrap());
+	let id_info = match client.verify(&bearer.0) {
+		Ok(res) => res,
+		Err(_) => return Err(ApiResponse {
+								json: json!({"message": "Unauthorized user"}),
+								status: Status::Unauthorized,
+							})
+	};
+	let mut subscriber: Subscriber = Subscriber{
+									id:None,
+									social_id:Some(id_info.sub),
+									proto:Some("google".to_string())
+									};
+	if let Err(e) = subscriber.set_id(conn) {
+		return Err(not_signed_in(e))
+	}
+	let subscriber_id = subscriber.id.unwrap();
+	match Admin::role(conn, &subscriber_id) {
+		Ok(ref role) if Admin::has_role(role, needed) => Ok(subscriber_id),
+		Ok(_role) => Err(ApiResponse {
+					json: json!({"message": "Forbidden"}),
+					status: Status::Forbidden,
+				}),
+		Err(e) => Err(ApiResponse {
+					json: json!({"message": String::from("Admin failed: ")+&e.details}),
+					status: Status::InternalServerError,
+				})
+	}
+}
+
+/// Staff only act on subscribers with a lower role than their own
+fn outranks(conn: &DbConn, actor: &String, subscriber_id: &String) -> Option<ApiResponse> {
+	match (Admin::role(conn, actor), Admin::role(conn, subscriber_id)) {
+		(Ok(ref role), Ok(ref other)) if !Admin::has_role(other, role) => None,
+		(Ok(_), Ok(_)) => Some(ApiResponse {
+					json: json!({"message": "Forbidden"}),
+					status: Status::Forbidden,
+				}),
+		_ => Some(ApiResponse {
+					json: json!({"message": "Admin failed: Role not found"}),
+					status: Status::InternalServerError,
+				})
+	}
+}
+
+/// A response carrying its ETag, a 304 if the client already has it.
+/// Without a given ETag the body is hashed
+#[derive(Debug)]
+pub struct TaggedResponse {
+    response: ApiResponse,
+    if_none_match: IfNoneMatch,
+    etag: Option<String>
+}
+
+impl<'r> Responder<'r> for TaggedResponse {
+    fn respond_to(self, req: &Request) -> response::Result<'r> {
+        if self.response.status != Status::Ok {
+            return self.response.respond_to(req)
+        }
+        let tag = match self.etag {
+            Some(tag) => tag,
+            None => etag::body(&self.response.json)
+        };
+        match self.if_none_match.0 {
+            Some(ref tags) if etag::matches(tags, &tag) => Response::build()
+                .status(Status::NotModified)
+                .raw_header("ETag", tag)
+                .ok(),
+            _ => Response::build_from(self.response.respond_to(req)?)
+                .raw_header("ETag", tag)
+                .ok()
+        }
+    }
+}
+
 #[post("/subscriber/register", format = "application/json")]
 pub fn subscriber_register(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
 	let mut client = google_signin::Client::new();
@@ -82,25 +258,77 @@ pub fn subscriber_register(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiR
 }
 
 #[get("/class/<id>/<subscriber_id>", format = "application/json")]
-pub fn get_class(conn: DbConn, id: String, _key: Result<Bearer, JsonValue>, subscriber_id:String) -> ApiResponse {
-	let subscriber: Subscriber = Subscriber{id:Some(subscriber_id),
-												social_id:None,
-												proto:None};
-	let mut class: Class = Class(id);
-	match class.get_class(conn, &subscriber.id.unwrap()) {
-		Ok(items) => return ApiResponse {
-					json: json!(items),
-					status: Status::Ok,
-				},
-		Err(e) => return ApiResponse {
-					json: json!({"message": String::from("Items failed: ")+&e.details}),
-					status: Status::NotFound,
-				}
+pub fn get_class(conn: DbConn, id: String, key: Result<Bearer, JsonValue>, subscriber_id:String, if_none_match: IfNoneMatch) -> TaggedResponse {
+	// Read before the items, a write in between only makes the ETag older
+	let revision = Revision::get(&conn, &subscriber_id, &id).ok();
+	TaggedResponse {
+		response: class_response(conn, id, key, subscriber_id),
+		if_none_match: if_none_match,
+		etag: revision.map(etag::revision)
 	}
 }
 
+fn class_response(conn: DbConn, id: String, key: Result<Bearer, JsonValue>, subscriber_id:String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					if !subscriber.can_view(&conn, &subscriber_id) {
+						return ApiResponse {
+							json: json!({"message": "Items failed: not visible"}),
+							status: Status::Forbidden,
+						}
+					}
+					let mut class: Class = Class(id);
+					match class.get_class(&conn, &subscriber_id) {
+						Ok(items) => return ApiResponse {
+									json: json!(items),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Items failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
 #[post("/class/<id>", format = "application/json", data="<items>")]
-pub fn post_class(conn: DbConn, id: String, key: Result<Bearer, JsonValue>, items: Json<Vec<Item>>) -> ApiResponse {
+pub fn post_class(conn: DbConn, catalog: State<LiveCatalog>, id: String, key: Result<Bearer, JsonValue>, client_id: ClientId, if_match: IfMatch, items: Json<Vec<Item>>) -> TaggedResponse {
+	let mut written = None;
+	let response = post_class_response(conn, catalog, id, key, client_id, if_match, items, &mut written);
+	TaggedResponse {
+		response: response,
+		if_none_match: IfNoneMatch(None),
+		etag: written
+	}
+}
+
+/// `written` gets the ETag of the class after the write
+fn post_class_response(conn: DbConn, catalog: State<LiveCatalog>, id: String, key: Result<Bearer, JsonValue>, client_id: ClientId, if_match: IfMatch, items: Json<Vec<Item>>, written: &mut Option<String>) -> ApiResponse {
+	let catalog = catalog.current();
 	match key {
 		Ok(bearer) => {
 			let mut client = google_signin::Client::new();
@@ -119,22 +347,25 @@ pub fn post_class(conn: DbConn, id: String, key: Result<Bearer, JsonValue>, item
 											};
 			match subscriber.set_id(&conn) {
 				Ok(()) => {
+						let subscriber_id = subscriber.id.unwrap();
 						let mut class: Class = Class(id);
-						match class.set_class(conn, &subscriber.id.unwrap(), items.into_inner()) {
-							Ok(_items) => return ApiResponse {
-										json: json!({"message": "Success"}),
+						match class.set_class(&conn, &subscriber_id, items.into_inner(), &client_id.0, if_match.0.as_ref()) {
+							Ok(revision) => {
+								*written = Some(etag::revision(revision));
+								let unlocked = Achievement::evaluate(&conn, &subscriber_id, &catalog).unwrap_or_default();
+								return ApiResponse {
+										json: json!({"message": "Success", "achievements": unlocked}),
 										status: Status::Ok,
-									},
+									}
+							},
+							Err(ref e) if e.is_changed() => return precondition_failed(),
 							Err(e) => return ApiResponse {
 										json: json!({"message": String::from("Items failed: ")+&e.details}),
 										status: Status::NotFound,
 									}
 						}
 					},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -145,7 +376,15 @@ pub fn post_class(conn: DbConn, id: String, key: Result<Bearer, JsonValue>, item
 }
 
 #[get("/friends", format = "application/json")]
-pub fn get_friends(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+pub fn get_friends(conn: DbConn, key: Result<Bearer, JsonValue>, if_none_match: IfNoneMatch) -> TaggedResponse {
+	TaggedResponse {
+		response: friends_response(conn, key),
+		if_none_match: if_none_match,
+		etag: None
+	}
+}
+
+fn friends_response(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
 	match key {
 		Ok(bearer) => {
 			let mut client = google_signin::Client::new();
@@ -175,10 +414,7 @@ pub fn get_friends(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse
 								}
 					}
 				},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -189,7 +425,19 @@ pub fn get_friends(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse
 }
 
 #[post("/friend", format = "application/json", data="<friend>")]
-pub fn post_friend(conn: DbConn, key: Result<Bearer, JsonValue>, friend: Json<Friend>) -> ApiResponse {
+pub fn post_friend(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, if_match: IfMatch, friend: Json<Friend>) -> TaggedResponse {
+	let mut written = None;
+	let response = post_friend_response(conn, catalog, key, if_match, friend, &mut written);
+	TaggedResponse {
+		response: response,
+		if_none_match: IfNoneMatch(None),
+		etag: written
+	}
+}
+
+/// `written` gets the ETag of the friends list after the write
+fn post_friend_response(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, if_match: IfMatch, friend: Json<Friend>, written: &mut Option<String>) -> ApiResponse {
+	let catalog = catalog.current();
 	match key {
 		Ok(bearer) => {
 			let mut client = google_signin::Client::new();
@@ -208,21 +456,23 @@ pub fn post_friend(conn: DbConn, key: Result<Bearer, JsonValue>, friend: Json<Fr
 											};
 			match subscriber.set_id(&conn) {
 				Ok(()) => {
-					match subscriber.set_friend(&conn, friend.into_inner()) {
-						Ok(_items) => return ApiResponse {
-									json: json!({"message": "Success"}),
+					match subscriber.set_friend(&conn, friend.into_inner(), if_match.0.as_ref()) {
+						Ok(tag) => {
+							*written = Some(tag);
+							let unlocked = Achievement::evaluate(&conn, &subscriber.id.unwrap(), &catalog).unwrap_or_default();
+							return ApiResponse {
+									json: json!({"message": "Success", "achievements": unlocked}),
 									status: Status::Ok,
-								},
+								}
+						},
+						Err(ref e) if e.is_changed() => return precondition_failed(),
 						Err(e) => return ApiResponse {
 									json: json!({"message": String::from("Friend failed: ")+&e.details}),
 									status: Status::NotFound,
 								}
 					}
 				},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -233,7 +483,18 @@ pub fn post_friend(conn: DbConn, key: Result<Bearer, JsonValue>, friend: Json<Fr
 }
 
 #[put("/friend", format = "application/json", data="<friend>")]
-pub fn update_friend(conn: DbConn, key: Result<Bearer, JsonValue>, friend: Json<Friend>) -> ApiResponse {
+pub fn update_friend(conn: DbConn, key: Result<Bearer, JsonValue>, if_match: IfMatch, friend: Json<Friend>) -> TaggedResponse {
+	let mut written = None;
+	let response = update_friend_response(conn, key, if_match, friend, &mut written);
+	TaggedResponse {
+		response: response,
+		if_none_match: IfNoneMatch(None),
+		etag: written
+	}
+}
+
+/// `written` gets the ETag of the friends list after the write
+fn update_friend_response(conn: DbConn, key: Result<Bearer, JsonValue>, if_match: IfMatch, friend: Json<Friend>, written: &mut Option<String>) -> ApiResponse {
 	match key {
 		Ok(bearer) => {
 			let mut client = google_signin::Client::new();
@@ -252,21 +513,22 @@ pub fn update_friend(conn: DbConn, key: Result<Bearer, JsonValue>, friend: Json<
 											};
 			match subscriber.set_id(&conn) {
 				Ok(()) => {
-					match subscriber.update_friend(&conn, friend.into_inner()) {
-						Ok(_items) => return ApiResponse {
+					match subscriber.update_friend(&conn, friend.into_inner(), if_match.0.as_ref()) {
+						Ok(tag) => {
+							*written = Some(tag);
+							return ApiResponse {
 									json: json!({"message": "Success"}),
 									status: Status::Ok,
-								},
+								}
+						},
+						Err(ref e) if e.is_changed() => return precondition_failed(),
 						Err(e) => return ApiResponse {
 									json: json!({"message": String::from("Friend failed: ")+&e.details}),
 									status: Status::NotFound,
 								}
 					}
 				},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -277,7 +539,18 @@ pub fn update_friend(conn: DbConn, key: Result<Bearer, JsonValue>, friend: Json<
 }
 
 #[delete("/friend/<id>", format = "application/json")]
-pub fn delete_friend(conn: DbConn, key: Result<Bearer, JsonValue>, id: String ) -> ApiResponse {
+pub fn delete_friend(conn: DbConn, key: Result<Bearer, JsonValue>, if_match: IfMatch, id: String ) -> TaggedResponse {
+	let mut written = None;
+	let response = delete_friend_response(conn, key, if_match, id, &mut written);
+	TaggedResponse {
+		response: response,
+		if_none_match: IfNoneMatch(None),
+		etag: written
+	}
+}
+
+/// `written` gets the ETag of the friends list after the write
+fn delete_friend_response(conn: DbConn, key: Result<Bearer, JsonValue>, if_match: IfMatch, id: String, written: &mut Option<String>) -> ApiResponse {
 	match key {
 		Ok(bearer) => {
 			let mut client = google_signin::Client::new();
@@ -296,21 +569,22 @@ pub fn delete_friend(conn: DbConn, key: Result<Bearer, JsonValue>, id: String )
 											};
 			match subscriber.set_id(&conn) {
 				Ok(()) => {
-					match subscriber.delete_friend(&conn, id) {
-						Ok(_items) => return ApiResponse {
+					match subscriber.delete_friend(&conn, id, if_match.0.as_ref()) {
+						Ok(tag) => {
+							*written = Some(tag);
+							return ApiResponse {
 									json: json!({"message": "Success"}),
 									status: Status::Ok,
-								},
+								}
+						},
+						Err(ref e) if e.is_changed() => return precondition_failed(),
 						Err(e) => return ApiResponse {
 									json: json!({"message": String::from("Friend failed: ")+&e.details}),
 									status: Status::NotFound,
 								}
 					}
 				},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -351,10 +625,7 @@ pub fn check_friend(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) ->
 								}
 					}
 				},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -365,7 +636,19 @@ pub fn check_friend(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) ->
 }
 
 #[post("/profile", format = "application/json", data="<profile>")]
-pub fn post_profile(conn: DbConn, key: Result<Bearer, JsonValue>, profile: Json<Profile>) -> ApiResponse {
+pub fn post_profile(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, if_match: IfMatch, profile: Json<Profile>) -> TaggedResponse {
+	let mut written = None;
+	let response = post_profile_response(conn, catalog, key, if_match, profile, &mut written);
+	TaggedResponse {
+		response: response,
+		if_none_match: IfNoneMatch(None),
+		etag: written
+	}
+}
+
+/// `written` gets the ETag of the profile after the write
+fn post_profile_response(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, if_match: IfMatch, profile: Json<Profile>, written: &mut Option<String>) -> ApiResponse {
+	let catalog = catalog.current();
 	match key {
 		Ok(bearer) => {
 			let mut client = google_signin::Client::new();
@@ -384,21 +667,23 @@ pub fn post_profile(conn: DbConn, key: Result<Bearer, JsonValue>, profile: Json<
 											};
 			match subscriber.set_id(&conn) {
 				Ok(()) => {
-					match subscriber.set_profile(&conn, profile.into_inner()) {
-						Ok(_items) => return ApiResponse {
-									json: json!({"message": "Success"}),
+					match subscriber.set_profile(&conn, profile.into_inner(), if_match.0.as_ref()) {
+						Ok(tag) => {
+							*written = Some(tag);
+							let unlocked = Achievement::evaluate(&conn, &subscriber.id.unwrap(), &catalog).unwrap_or_default();
+							return ApiResponse {
+									json: json!({"message": "Success", "achievements": unlocked}),
 									status: Status::Ok,
-								},
+								}
+						},
+						Err(ref e) if e.is_changed() => return precondition_failed(),
 						Err(e) => return ApiResponse {
 									json: json!({"message": String::from("Profile failed: ")+&e.details}),
 									status: Status::NotFound,
 								}
 					}
 				},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -409,7 +694,15 @@ pub fn post_profile(conn: DbConn, key: Result<Bearer, JsonValue>, profile: Json<
 }
 
 #[get("/profile", format = "application/json")]
-pub fn get_profile(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+pub fn get_profile(conn: DbConn, key: Result<Bearer, JsonValue>, if_none_match: IfNoneMatch) -> TaggedResponse {
+	TaggedResponse {
+		response: profile_response(conn, key),
+		if_none_match: if_none_match,
+		etag: None
+	}
+}
+
+fn profile_response(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
 	match key {
 		Ok(bearer) => {
 			let mut client = google_signin::Client::new();
@@ -439,10 +732,7 @@ pub fn get_profile(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse
 								}
 					}
 				},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -483,10 +773,131 @@ pub fn get_other_profile(conn: DbConn, id: String, key: Result<Bearer, JsonValue
 								}
 					}
 				},
-				Err(_e) => return ApiResponse {
-					json: json!({"message":"Subscriber not found"}),
-					status: Status::NotFound,
-				}
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/trades/suggestions", format = "application/json")]
+pub fn get_trade_suggestions(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match TradeSuggestion::for_subscriber(&conn, &mut subscriber) {
+						Ok(suggestions) => return ApiResponse {
+								json: json!(suggestions),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Suggestions failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/trade", format = "application/json", data="<trade>")]
+pub fn post_trade(conn: DbConn, key: Result<Bearer, JsonValue>, trade: Json<Trade>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let mut trade = trade.into_inner();
+					match trade.create(&conn, &mut subscriber) {
+						Ok(()) => return ApiResponse {
+									json: json!(trade),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Trade failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/trades", format = "application/json")]
+pub fn get_trades(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Trade::list(&conn, &subscriber.id.unwrap()) {
+						Ok(trades) => return ApiResponse {
+								json: json!(trades),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Trades failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
 			}
 		},
 		Err(json_error) => return ApiResponse {
@@ -495,3 +906,3188 @@ pub fn get_other_profile(conn: DbConn, id: String, key: Result<Bearer, JsonValue
 		}
 	};
 }
+
+#[get("/trade/<id>", format = "application/json")]
+pub fn get_trade(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Trade::get(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(trade) => return ApiResponse {
+								json: json!(trade),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Trade failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[put("/trade/<id>/<action>", format = "application/json")]
+pub fn update_trade(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, id: String, action: String) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let subscriber_id = subscriber.id.unwrap();
+					let mut trade = match Trade::get(&conn, &subscriber_id, &id) {
+						Ok(trade) => trade,
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Trade failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					};
+					match trade.update_state(&conn, &subscriber_id, &action) {
+						Ok(()) => {
+							// Both parties get their trade counted
+							if trade.state.as_ref().map(|state| state.as_str()) == Some("completed") {
+								let _ = Achievement::evaluate(&conn, &trade.to, &catalog);
+								if let Some(ref from) = trade.from {
+									let _ = Achievement::evaluate(&conn, from, &catalog);
+								}
+							}
+							return ApiResponse {
+									json: json!(trade),
+									status: Status::Ok,
+								}
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Trade failed: ")+&e.details}),
+									status: Status::Conflict,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/search/item/<class>/<item_id>", format = "application/json")]
+pub fn search_item(conn: DbConn, key: Result<Bearer, JsonValue>, class: String, item_id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let mut class: Class = Class(class);
+					match class.who_has(&conn, &mut subscriber, &item_id) {
+						Ok(holders) => return ApiResponse {
+								json: json!(holders),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Search failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/wishlist", format = "application/json")]
+pub fn get_wishlist(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Wishlist::get(&conn, &subscriber.id.unwrap()) {
+						Ok(items) => return ApiResponse {
+								json: json!(items),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Wishlist failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/wishlist/<subscriber_id>", format = "application/json")]
+pub fn get_other_wishlist(conn: DbConn, key: Result<Bearer, JsonValue>, subscriber_id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					if !subscriber.is_friend_of(&conn, &subscriber_id) {
+						return ApiResponse {
+							json: json!({"message": "Wishlist failed: not in friends list"}),
+							status: Status::Forbidden,
+						}
+					}
+					match Wishlist::get(&conn, &subscriber_id) {
+						Ok(items) => return ApiResponse {
+								json: json!(items),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Wishlist failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/wishlist", format = "application/json", data="<item>")]
+pub fn post_wishlist(conn: DbConn, key: Result<Bearer, JsonValue>, item: Json<WishlistItem>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Wishlist::set(&conn, &subscriber.id.unwrap(), item.into_inner()) {
+						Ok(()) => return ApiResponse {
+									json: json!({"message": "Success"}),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Wishlist failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/wishlist/<class>/<item_id>", format = "application/json")]
+pub fn delete_wishlist(conn: DbConn, key: Result<Bearer, JsonValue>, class: String, item_id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Wishlist::delete(&conn, &subscriber.id.unwrap(), &class, &item_id) {
+						Ok(()) => return ApiResponse {
+									json: json!({"message": "Success"}),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Wishlist failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/listings?<query..>", format = "application/json")]
+pub fn get_listings(conn: DbConn, key: Result<Bearer, JsonValue>, query: LenientForm<ListingQuery>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					if let Err(e) = query.validate() {
+						return ApiResponse {
+							json: json!({"message": String::from("Listings failed: ")+&e.details}),
+							status: Status::BadRequest,
+						}
+					}
+					match Listing::search(&conn, &query) {
+						Ok(listings) => return ApiResponse {
+								json: json!(listings),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Listings failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/listings/mine", format = "application/json")]
+pub fn get_my_listings(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Listing::list_owned(&conn, &subscriber.id.unwrap()) {
+						Ok(listings) => return ApiResponse {
+								json: json!(listings),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Listings failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/listing/<id>", format = "application/json")]
+pub fn get_listing(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Listing::get(&conn, &id) {
+						Ok(listing) => return ApiResponse {
+								json: json!(listing),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Listing failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/listing", format = "application/json", data="<listing>")]
+pub fn post_listing(conn: DbConn, key: Result<Bearer, JsonValue>, listing: Json<Listing>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let mut listing = listing.into_inner();
+					match listing.create(&conn, &subscriber.id.unwrap()) {
+						Ok(()) => return ApiResponse {
+									json: json!(listing),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Listing failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[put("/listing/<id>", format = "application/json", data="<listing>")]
+pub fn update_listing(conn: DbConn, key: Result<Bearer, JsonValue>, id: String, listing: Json<Listing>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let mut listing = listing.into_inner();
+					match listing.update(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(()) => return ApiResponse {
+									json: json!(listing),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Listing failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/listing/<id>", format = "application/json")]
+pub fn delete_listing(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Listing::delete(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(()) => return ApiResponse {
+									json: json!({"message": "Success"}),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Listing failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/turnips", format = "application/json")]
+pub fn get_turnips(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let (week, _period) = turnip::current_period(&subscriber.get_timezone(&conn));
+					match TurnipWeek::get(&conn, &subscriber.id.unwrap(), &week) {
+						Ok(turnips) => return ApiResponse {
+								json: json!(turnips),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Turnips failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/turnips/weeks", format = "application/json")]
+pub fn get_turnip_weeks(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match TurnipWeek::list(&conn, &subscriber.id.unwrap()) {
+						Ok(weeks) => return ApiResponse {
+								json: json!(weeks),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Turnips failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/turnips/week/<week>", format = "application/json")]
+pub fn get_turnip_week(conn: DbConn, key: Result<Bearer, JsonValue>, week: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match TurnipWeek::get(&conn, &subscriber.id.unwrap(), &week) {
+						Ok(turnips) => return ApiResponse {
+								json: json!(turnips),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Turnips failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/turnips", format = "application/json", data="<price>")]
+pub fn post_turnips(conn: DbConn, key: Result<Bearer, JsonValue>, price: Json<TurnipPrice>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let (week, period) = turnip::current_period(&subscriber.get_timezone(&conn));
+					let price = price.into_inner();
+					let period = price.period.unwrap_or(period);
+					match TurnipWeek::set_price(&conn, &subscriber.id.unwrap(), &week, &period, price.price) {
+						Ok(()) => return ApiResponse {
+									json: json!({"message": "Success"}),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Turnips failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[put("/turnips/week/<week>", format = "application/json", data="<price>")]
+pub fn update_turnips(conn: DbConn, key: Result<Bearer, JsonValue>, week: String, price: Json<TurnipPrice>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let price = price.into_inner();
+					let period = match price.period {
+						Some(period) => period,
+						None => return ApiResponse {
+									json: json!({"message": "Turnips failed: Missing period"}),
+									status: Status::UnprocessableEntity,
+								}
+					};
+					match TurnipWeek::set_price(&conn, &subscriber.id.unwrap(), &week, &period, price.price) {
+						Ok(()) => return ApiResponse {
+									json: json!({"message": "Success"}),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Turnips failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/turnips/prediction", format = "application/json")]
+pub fn get_turnip_prediction(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let (week, _period) = turnip::current_period(&subscriber.get_timezone(&conn));
+					match TurnipPrediction::for_week(&conn, &subscriber.id.unwrap(), &week) {
+						Ok(prediction) => return ApiResponse {
+								json: json!(prediction),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Prediction failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/turnips/friends", format = "application/json")]
+pub fn get_turnip_board(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match BoardEntry::for_subscriber(&conn, &mut subscriber) {
+						Ok(board) => return ApiResponse {
+								json: json!(board),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Turnips failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/turnips/gates", format = "application/json", data="<gates>")]
+pub fn post_gates(conn: DbConn, key: Result<Bearer, JsonValue>, gates: Json<Gates>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let mut gates = gates.into_inner();
+					match gates.open(&conn, &subscriber.id.unwrap()) {
+						Ok(()) => return ApiResponse {
+									json: json!(gates),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Gates failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/turnips/gates", format = "application/json")]
+pub fn delete_gates(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Gates::close(&conn, &subscriber.id.unwrap()) {
+						Ok(()) => return ApiResponse {
+									json: json!({"message": "Success"}),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Gates failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/queue", format = "application/json", data="<session>")]
+pub fn post_queue(conn: DbConn, key: Result<Bearer, JsonValue>, session: Json<Session>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let mut session = session.into_inner();
+					match session.open(&conn, &subscriber.id.unwrap()) {
+						Ok(()) => return ApiResponse {
+								json: json!(session),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Queue failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/queue", format = "application/json")]
+pub fn get_hosted_queue(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Session::hosted(&conn, &subscriber.id.unwrap()) {
+						Ok(session) => return ApiResponse {
+								json: json!(session),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Queue failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/queue/<id>", format = "application/json")]
+pub fn get_queue(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Session::get(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(session) => return ApiResponse {
+								json: json!(session),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Queue failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/queue/<id>", format = "application/json")]
+pub fn delete_queue(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Session::close(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(()) => return ApiResponse {
+								json: json!({"message": "Success"}),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Queue failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/queue/<id>/join", format = "application/json")]
+pub fn join_queue(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Session::join(&conn, &subscriber, &id) {
+						Ok(session) => return ApiResponse {
+								json: json!(session),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Queue failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/queue/<id>/join", format = "application/json")]
+pub fn leave_queue(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Session::leave(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(()) => return ApiResponse {
+								json: json!({"message": "Success"}),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Queue failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/queue/<id>/next", format = "application/json")]
+pub fn advance_queue(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Session::advance(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(session) => return ApiResponse {
+								json: json!(session),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Queue failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/villagers", format = "application/json")]
+pub fn get_villagers(catalog: State<LiveCatalog>) -> ApiResponse {
+	let catalog = catalog.current();
+	ApiResponse {
+		json: json!(catalog.villagers),
+		status: Status::Ok,
+	}
+}
+
+#[get("/villagers/<villager>/friends?<status>", format = "application/json")]
+pub fn get_villager_friends(conn: DbConn, key: Result<Bearer, JsonValue>, villager: String, status: Option<String>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Resident::friends_with(&conn, &mut subscriber, &villager, status) {
+						Ok(holders) => return ApiResponse {
+								json: json!(holders),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Villagers failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/residents", format = "application/json")]
+pub fn get_residents(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Resident::get_all(&conn, &subscriber.id.unwrap()) {
+						Ok(residents) => return ApiResponse {
+								json: json!(residents),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Villagers failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/resident", format = "application/json", data="<resident>")]
+pub fn post_resident(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, resident: Json<Resident>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let mut resident = resident.into_inner();
+					match resident.set(&conn, &subscriber.id.unwrap(), &catalog) {
+						Ok(()) => return ApiResponse {
+								json: json!(resident),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Villager failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/resident/<villager>", format = "application/json")]
+pub fn delete_resident(conn: DbConn, key: Result<Bearer, JsonValue>, villager: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Resident::delete(&conn, &subscriber.id.unwrap(), &villager) {
+						Ok(()) => return ApiResponse {
+								json: json!({"message": "Success"}),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Villager failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/dreamies", format = "application/json")]
+pub fn get_dreamies(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Dreamies::get(&conn, &subscriber.id.unwrap()) {
+						Ok(dreamies) => return ApiResponse {
+								json: json!(dreamies),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Dreamies failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/dreamie/<villager>", format = "application/json")]
+pub fn post_dreamie(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, villager: String) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Dreamies::add(&conn, &subscriber.id.unwrap(), &villager, &catalog) {
+						Ok(()) => return ApiResponse {
+								json: json!({"message": "Success"}),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Dreamies failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/dreamie/<villager>", format = "application/json")]
+pub fn delete_dreamie(conn: DbConn, key: Result<Bearer, JsonValue>, villager: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Dreamies::delete(&conn, &subscriber.id.unwrap(), &villager) {
+						Ok(()) => return ApiResponse {
+								json: json!({"message": "Success"}),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Dreamies failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/recipes", format = "application/json")]
+pub fn get_recipes(catalog: State<LiveCatalog>) -> ApiResponse {
+	let catalog = catalog.current();
+	ApiResponse {
+		json: json!(catalog.recipes),
+		status: Status::Ok,
+	}
+}
+
+#[get("/recipes/mine", format = "application/json")]
+pub fn get_my_recipes(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match RecipeCard::get_all(&conn, &subscriber.id.unwrap()) {
+						Ok(cards) => return ApiResponse {
+								json: json!(cards),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Recipes failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/recipes", format = "application/json", data="<cards>")]
+pub fn post_recipes(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, cards: Json<Vec<RecipeCard>>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let subscriber_id = subscriber.id.unwrap();
+					match RecipeCard::set(&conn, &subscriber_id, &cards.into_inner(), &catalog) {
+						Ok(()) => {
+							let unlocked = Achievement::evaluate(&conn, &subscriber_id, &catalog).unwrap_or_default();
+							return ApiResponse {
+								json: json!({"message": "Success", "achievements": unlocked}),
+								status: Status::Ok
+							}
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Recipes failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/recipes/learn", format = "application/json", data="<ids>")]
+pub fn learn_recipes(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, ids: Json<Vec<String>>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let subscriber_id = subscriber.id.unwrap();
+					match RecipeCard::learn(&conn, &subscriber_id, &ids.into_inner(), true, &catalog) {
+						Ok(()) => {
+							let unlocked = Achievement::evaluate(&conn, &subscriber_id, &catalog).unwrap_or_default();
+							return ApiResponse {
+								json: json!({"message": "Success", "achievements": unlocked}),
+								status: Status::Ok
+							}
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Recipes failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/recipes/unlearn", format = "application/json", data="<ids>")]
+pub fn unlearn_recipes(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, ids: Json<Vec<String>>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match RecipeCard::learn(&conn, &subscriber.id.unwrap(), &ids.into_inner(), false, &catalog) {
+						Ok(()) => return ApiResponse {
+								json: json!({"message": "Success"}),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Recipes failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/recipes/stats", format = "application/json")]
+pub fn get_recipe_stats(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match RecipeCard::stats(&conn, &subscriber.id.unwrap(), &catalog) {
+						Ok(stats) => return ApiResponse {
+								json: json!(stats),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Recipes failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/recipes/spares", format = "application/json")]
+pub fn get_friend_spares(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match RecipeCard::friend_spares(&conn, &mut subscriber) {
+						Ok(holders) => return ApiResponse {
+								json: json!(holders),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Recipes failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/recipes/spares/<subscriber_id>", format = "application/json")]
+pub fn get_spares(conn: DbConn, key: Result<Bearer, JsonValue>, subscriber_id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					if !subscriber.can_view(&conn, &subscriber_id) {
+						return ApiResponse {
+							json: json!({"message": "Recipes failed: not visible"}),
+							status: Status::Forbidden,
+						}
+					}
+					match RecipeCard::spares(&conn, &subscriber_id) {
+						Ok(cards) => return ApiResponse {
+								json: json!(cards),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Recipes failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/events?<query..>", format = "application/json")]
+pub fn get_events(catalog: State<LiveCatalog>, query: LenientForm<EventQuery>) -> ApiResponse {
+	let catalog = catalog.current();
+	match EventDates::search(&catalog, &query, Utc::today().naive_utc()) {
+		Ok(events) => ApiResponse {
+			json: json!(events),
+			status: Status::Ok,
+		},
+		Err(e) => ApiResponse {
+			json: json!({"message": String::from("Events failed: ")+&e.details}),
+			status: Status::BadRequest,
+		}
+	}
+}
+
+#[get("/events/mine?<query..>", format = "application/json")]
+pub fn get_my_events(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, query: LenientForm<EventQuery>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match EventDates::for_subscriber(&conn, &subscriber, &catalog, &mut query.into_inner()) {
+						Ok(events) => return ApiResponse {
+								json: json!(events),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Events failed: ")+&e.details}),
+									status: Status::BadRequest,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/events/needed?<query..>", format = "application/json")]
+pub fn get_needed_events(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, query: LenientForm<EventQuery>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match EventDates::needed(&conn, &subscriber, &catalog, &mut query.into_inner()) {
+						Ok(events) => return ApiResponse {
+								json: json!(events),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Events failed: ")+&e.details}),
+									status: Status::BadRequest,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/checklist", format = "application/json")]
+pub fn get_checklist(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Checklist::get(&conn, &subscriber) {
+						Ok(checklist) => return ApiResponse {
+								json: json!(checklist),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Checklist failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[put("/checklist/<task>", format = "application/json")]
+pub fn toggle_checklist(conn: DbConn, key: Result<Bearer, JsonValue>, task: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Checklist::toggle(&conn, &subscriber, &task) {
+						Ok(checklist) => return ApiResponse {
+								json: json!(checklist),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Checklist failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/checklist", format = "application/json", data="<tasks>")]
+pub fn post_checklist(conn: DbConn, key: Result<Bearer, JsonValue>, tasks: Json<Vec<Task>>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Checklist::set_tasks(&conn, &subscriber, tasks.into_inner()) {
+						Ok(checklist) => return ApiResponse {
+								json: json!(checklist),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Checklist failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/checklist", format = "application/json")]
+pub fn delete_checklist(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Checklist::reset_tasks(&conn, &subscriber) {
+						Ok(checklist) => return ApiResponse {
+								json: json!(checklist),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Checklist failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/leaderboard?<page>", format = "application/json")]
+pub fn get_leaderboard(conn: DbConn, key: Result<Bearer, JsonValue>, page: Option<usize>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Leaderboard::global(&conn, &subscriber.id.unwrap(), &None, page.unwrap_or(0)) {
+						Ok(entries) => return ApiResponse {
+								json: json!(entries),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Leaderboard failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/leaderboard/friends?<page>", format = "application/json")]
+pub fn get_friends_leaderboard(conn: DbConn, key: Result<Bearer, JsonValue>, page: Option<usize>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Leaderboard::friends(&conn, &mut subscriber, &None, page.unwrap_or(0)) {
+						Ok(entries) => return ApiResponse {
+								json: json!(entries),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Leaderboard failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/leaderboard/class/<class>?<page>", format = "application/json")]
+pub fn get_class_leaderboard(conn: DbConn, key: Result<Bearer, JsonValue>, class: String, page: Option<usize>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Leaderboard::global(&conn, &subscriber.id.unwrap(), &Some(class), page.unwrap_or(0)) {
+						Ok(entries) => return ApiResponse {
+								json: json!(entries),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Leaderboard failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/leaderboard/class/<class>/friends?<page>", format = "application/json")]
+pub fn get_class_friends_leaderboard(conn: DbConn, key: Result<Bearer, JsonValue>, class: String, page: Option<usize>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Leaderboard::friends(&conn, &mut subscriber, &Some(class), page.unwrap_or(0)) {
+						Ok(entries) => return ApiResponse {
+								json: json!(entries),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Leaderboard failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/achievements", format = "application/json")]
+pub fn get_achievements(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let subscriber_id = subscriber.id.unwrap();
+					// Rules added since the last change get a chance too
+					if let Err(e) = Achievement::evaluate(&conn, &subscriber_id, &catalog) {
+						return ApiResponse {
+							json: json!({"message": String::from("Achievements failed: ")+&e.details}),
+							status: Status::NotFound,
+						}
+					}
+					match Achievement::get_all(&conn, &subscriber_id, &catalog) {
+						Ok(achievements) => return ApiResponse {
+								json: json!(achievements),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Achievements failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/history?<query..>", format = "application/json")]
+pub fn get_history(conn: DbConn, key: Result<Bearer, JsonValue>, query: LenientForm<HistoryQuery>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					if let Err(e) = query.range() {
+						return ApiResponse {
+							json: json!({"message": String::from("History failed: ")+&e.details}),
+							status: Status::BadRequest,
+						}
+					}
+					match History::get(&conn, &subscriber.id.unwrap(), &query) {
+						Ok(entries) => return ApiResponse {
+								json: json!(entries),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("History failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/snapshots", format = "application/json")]
+pub fn get_snapshots(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Snapshot::list(&conn, &subscriber.id.unwrap()) {
+						Ok(snapshots) => return ApiResponse {
+								json: json!(snapshots),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Snapshots failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/snapshot", format = "application/json", data="<snapshot>")]
+pub fn post_snapshot(conn: DbConn, key: Result<Bearer, JsonValue>, snapshot: Json<Snapshot>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let mut snapshot = snapshot.into_inner();
+					snapshot.auto = false;
+					match snapshot.create(&conn, &subscriber.id.unwrap()) {
+						Ok(()) => return ApiResponse {
+									json: json!(snapshot),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Snapshot failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/snapshot/<id>/diff", format = "application/json")]
+pub fn get_snapshot_diff(conn: DbConn, id: String, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Snapshot::diff(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(changes) => return ApiResponse {
+								json: json!(changes),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Diff failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/snapshot/<id>/restore", format = "application/json")]
+pub fn restore_snapshot(conn: DbConn, catalog: State<LiveCatalog>, id: String, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let subscriber_id = subscriber.id.unwrap();
+					match Snapshot::restore(&conn, &subscriber_id, &id) {
+						Ok(changes) => {
+							let unlocked = Achievement::evaluate(&conn, &subscriber_id, &catalog).unwrap_or_default();
+							return ApiResponse {
+									json: json!({"message": "Success", "changes": changes, "achievements": unlocked}),
+									status: Status::Ok,
+								}
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Restore failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/snapshot/<id>", format = "application/json")]
+pub fn delete_snapshot(conn: DbConn, id: String, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Snapshot::delete(&conn, &subscriber.id.unwrap(), &id) {
+						Ok(()) => return ApiResponse {
+								json: json!({"message": "Success"}),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Snapshot failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[post("/sync/<class>", format = "application/json", data="<request>")]
+pub fn post_sync(conn: DbConn, catalog: State<LiveCatalog>, class: String, key: Result<Bearer, JsonValue>, client_id: ClientId, request: Json<SyncRequest>) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let subscriber_id = subscriber.id.unwrap();
+					match sync::sync(&conn, &subscriber_id, &class, request.into_inner(), &client_id.0) {
+						Ok(response) => {
+							let unlocked = Achievement::evaluate(&conn, &subscriber_id, &catalog).unwrap_or_default();
+							return ApiResponse {
+									json: json!({
+										"revision": response.revision,
+										"applied": response.applied,
+										"conflicts": response.conflicts,
+										"changes": response.changes,
+										"achievements": unlocked
+									}),
+									status: Status::Ok,
+								}
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Sync failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/inventory", format = "application/json")]
+pub fn get_inventory(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Class::get_all(&conn, &subscriber.id.unwrap()) {
+						Ok(classes) => return ApiResponse {
+								json: json!(classes),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Inventory failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/inventory/<subscriber_id>", format = "application/json")]
+pub fn get_other_inventory(conn: DbConn, key: Result<Bearer, JsonValue>, subscriber_id: String) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					if !subscriber.can_view(&conn, &subscriber_id) {
+						return ApiResponse {
+							json: json!({"message": "Inventory failed: not visible"}),
+							status: Status::Forbidden,
+						}
+					}
+					match Class::get_all(&conn, &subscriber_id) {
+						Ok(classes) => return ApiResponse {
+								json: json!(classes),
+								status: Status::Ok
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Inventory failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/export?<format>")]
+pub fn get_export(conn: DbConn, key: Result<Bearer, JsonValue>, format: Option<String>) -> Result<Content<String>, ApiResponse> {
+	let format = format.unwrap_or("json".to_string());
+	if !transfer::FORMATS.contains(&format.as_str()) {
+		return Err(ApiResponse {
+			json: json!({"message": "Export failed: Invalid format"}),
+			status: Status::BadRequest,
+		})
+	}
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return Err(ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									})
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match Export::get(&conn, &mut subscriber) {
+						Ok(export) => match format.as_str() {
+							"csv" => return Ok(Content(ContentType::CSV, export.to_csv())),
+							_ => return Ok(Content(ContentType::JSON, json!(export).to_string()))
+						},
+						Err(e) => return Err(ApiResponse {
+									json: json!({"message": String::from("Export failed: ")+&e.details}),
+									status: Status::NotFound,
+								})
+					}
+				},
+				Err(e) => return Err(not_signed_in(e))
+			}
+		},
+		Err(json_error) => return Err(ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		})
+	};
+}
+
+#[post("/import?<format>&<dry_run>", data="<data>")]
+pub fn post_import(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>, client_id: ClientId, format: Option<String>, dry_run: Option<bool>, data: Data) -> ApiResponse {
+	let catalog = catalog.current();
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					// One byte past the limit tells a full body from a cut off one
+					let mut bytes: Vec<u8> = Vec::new();
+					if data.open().take(IMPORT_LIMIT + 1).read_to_end(&mut bytes).is_err() {
+						return ApiResponse {
+							json: json!({"message": "Import failed: Body couldn't be read"}),
+							status: Status::BadRequest,
+						}
+					}
+					if bytes.len() as u64 > IMPORT_LIMIT {
+						return ApiResponse {
+							json: json!({"message": "Import failed: File too large"}),
+							status: Status::PayloadTooLarge,
+						}
+					}
+					let body = match String::from_utf8(bytes) {
+						Ok(body) => body,
+						Err(_e) => return ApiResponse {
+							json: json!({"message": "Import failed: Invalid encoding"}),
+							status: Status::BadRequest,
+						}
+					};
+					let parsed = match format.as_ref().map(|format| format.as_str()) {
+						Some("csv") => Export::from_csv(&body),
+						Some("json") | None => Export::from_json(&body),
+						Some(_) => return ApiResponse {
+							json: json!({"message": "Import failed: Invalid format"}),
+							status: Status::BadRequest,
+						}
+					};
+					let (export, skipped) = match parsed {
+						Ok(parsed) => parsed,
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Import failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					};
+					let dry_run = dry_run.unwrap_or(false);
+					match export.import(&conn, &mut subscriber, skipped, dry_run, &client_id.0) {
+						Ok(ref report) if report.error.is_some() => return ApiResponse {
+									json: json!({"message": String::from("Import failed: ")+report.error.as_ref().unwrap(), "report": report}),
+									status: Status::InternalServerError,
+								},
+						Ok(report) => {
+							let unlocked = if dry_run {
+								Vec::new()
+							} else {
+								Achievement::evaluate(&conn, &subscriber.id.unwrap(), &catalog).unwrap_or_default()
+							};
+							return ApiResponse {
+									json: json!({"report": report, "achievements": unlocked}),
+									status: Status::Ok,
+								}
+						},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Import failed: ")+&e.details}),
+									status: Status::UnprocessableEntity,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[delete("/subscriber", format = "application/json", data="<reauth>")]
+pub fn delete_subscriber(conn: DbConn, key: Result<Bearer, JsonValue>, reauth: Json<Reauth>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let confirmed = match client.verify(&reauth.token) {
+						Ok(info) => Some(info.sub.to_owned()) == subscriber.social_id
+									&& info.iat.to_string().parse::<u64>().map_or(false, Account::is_fresh),
+						Err(_) => false
+					};
+					if !confirmed {
+						return ApiResponse {
+							json: json!({"message": "Deletion failed: sign in again to confirm"}),
+							status: Status::Unauthorized,
+						}
+					}
+					match Account::delete(&conn, &subscriber) {
+						Ok(()) => return ApiResponse {
+									json: json!({"message": "Success"}),
+									status: Status::Ok,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Deletion failed: ")+&e.details}),
+									status: Status::InternalServerError,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/subscriber/data-export")]
+pub fn get_data_export(conn: DbConn, pool: State<DbConnPool>, key: Result<Bearer, JsonValue>) -> Result<Content<File>, ApiResponse> {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return Err(ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									})
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					let subscriber_id = subscriber.id.unwrap();
+					match DataExport::request(&conn, &pool, &subscriber_id) {
+						Ok(export) => {
+							// A fresh archive is downloaded, otherwise it's on its way
+							if export.is_fresh() {
+								if let Ok(file) = File::open(DataExport::path(&subscriber_id, &export.id)) {
+									return Ok(Content(ContentType::new("application", "zip"), file))
+								}
+							}
+							return Err(ApiResponse {
+									json: json!(export),
+									status: Status::Accepted,
+								})
+						},
+						Err(e) => return Err(ApiResponse {
+									json: json!({"message": String::from("Data export failed: ")+&e.details}),
+									status: Status::InternalServerError,
+								})
+					}
+				},
+				Err(e) => return Err(not_signed_in(e))
+			}
+		},
+		Err(json_error) => return Err(ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		})
+	};
+}
+
+#[get("/subscriber/data-export/status", format = "application/json")]
+pub fn get_data_export_status(conn: DbConn, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	match key {
+		Ok(bearer) => {
+			let mut client = google_signin::Client::new();
+			client.audiences.push(env::var("GGL_KEY").unwrap());
+			let id_info = match client.verify(&bearer.0) {
+				Ok(res) => res,
+				Err(_) => return ApiResponse {
+										json: json!({"message": "Unauthorized user"}),
+										status: Status::Unauthorized,
+									}
+			};
+			let mut subscriber: Subscriber = Subscriber{
+											id:None,
+											social_id:Some(id_info.sub),
+											proto:Some("google".to_string())
+											};
+			match subscriber.set_id(&conn) {
+				Ok(()) => {
+					match DataExport::status(&conn, &subscriber.id.unwrap()) {
+						Ok(Some(export)) => return ApiResponse {
+								json: json!(export),
+								status: Status::Ok
+						},
+						Ok(None) => return ApiResponse {
+									json: json!({"message": "Data export failed: Export not found"}),
+									status: Status::NotFound,
+								},
+						Err(e) => return ApiResponse {
+									json: json!({"message": String::from("Data export failed: ")+&e.details}),
+									status: Status::NotFound,
+								}
+					}
+				},
+				Err(e) => return not_signed_in(e)
+			}
+		},
+		Err(json_error) => return ApiResponse {
+			json: json_error,
+			status: Status::BadRequest
+		}
+	};
+}
+
+#[get("/admin/subscribers?<search>", format = "application/json")]
+pub fn admin_search(conn: DbConn, key: Result<Bearer, JsonValue>, search: String) -> ApiResponse {
+	let actor = match staff(&conn, key, "moderator") {
+		Ok(actor) => actor,
+		Err(response) => return response
+	};
+	match Admin::search(&conn, &search) {
+		Ok(subscribers) => {
+			let _ = Admin::audit(&conn, &actor, "search", &String::new(), &search);
+			return ApiResponse {
+				json: json!(subscribers),
+				status: Status::Ok
+			}
+		},
+		Err(e) => return ApiResponse {
+					json: json!({"message": String::from("Search failed: ")+&e.details}),
+					status: Status::NotFound,
+				}
+	}
+}
+
+#[get("/admin/subscriber/<id>", format = "application/json")]
+pub fn admin_lookup(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	let actor = match staff(&conn, key, "moderator") {
+		Ok(actor) => actor,
+		Err(response) => return response
+	};
+	match Admin::lookup(&conn, &id) {
+		Ok(lookup) => {
+			let _ = Admin::audit(&conn, &actor, "lookup", &id, "");
+			return ApiResponse {
+				json: json!(lookup),
+				status: Status::Ok
+			}
+		},
+		Err(e) => return ApiResponse {
+					json: json!({"message": String::from("Lookup failed: ")+&e.details}),
+					status: Status::NotFound,
+				}
+	}
+}
+
+#[post("/admin/subscriber/<id>/suspend", format = "application/json", data="<suspension>")]
+pub fn admin_suspend(conn: DbConn, key: Result<Bearer, JsonValue>, id: String, suspension: Json<Suspension>) -> ApiResponse {
+	let actor = match staff(&conn, key, "moderator") {
+		Ok(actor) => actor,
+		Err(response) => return response
+	};
+	if let Some(response) = outranks(&conn, &actor, &id) {
+		return response
+	}
+	match Admin::suspend(&conn, &actor, &id, &suspension.reason) {
+		Ok(()) => return ApiResponse {
+					json: json!({"message": "Success"}),
+					status: Status::Ok
+				},
+		Err(e) => return ApiResponse {
+					json: json!({"message": String::from("Suspension failed: ")+&e.details}),
+					status: Status::UnprocessableEntity,
+				}
+	}
+}
+
+#[post("/admin/subscriber/<id>/unsuspend", format = "application/json")]
+pub fn admin_unsuspend(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	let actor = match staff(&conn, key, "moderator") {
+		Ok(actor) => actor,
+		Err(response) => return response
+	};
+	match Admin::unsuspend(&conn, &actor, &id) {
+		Ok(()) => return ApiResponse {
+					json: json!({"message": "Success"}),
+					status: Status::Ok
+				},
+		Err(e) => return ApiResponse {
+					json: json!({"message": String::from("Unsuspension failed: ")+&e.details}),
+					status: Status::UnprocessableEntity,
+				}
+	}
+}
+
+#[post("/admin/subscriber/<id>/reset-name", format = "application/json")]
+pub fn admin_reset_name(conn: DbConn, key: Result<Bearer, JsonValue>, id: String) -> ApiResponse {
+	let actor = match staff(&conn, key, "moderator") {
+		Ok(actor) => actor,
+		Err(response) => return response
+	};
+	if let Some(response) = outranks(&conn, &actor, &id) {
+		return response
+	}
+	match Admin::reset_name(&conn, &actor, &id) {
+		Ok(name) => return ApiResponse {
+					json: json!({"message": "Success", "name": name}),
+					status: Status::Ok
+				},
+		Err(e) => return ApiResponse {
+					json: json!({"message": String::from("Name reset failed: ")+&e.details}),
+					status: Status::NotFound,
+				}
+	}
+}
+
+#[post("/admin/subscriber/<id>/role", format = "application/json", data="<change>")]
+pub fn admin_set_role(conn: DbConn, key: Result<Bearer, JsonValue>, id: String, change: Json<RoleChange>) -> ApiResponse {
+	let actor = match staff(&conn, key, "admin") {
+		Ok(actor) => actor,
+		Err(response) => return response
+	};
+	// Otherwise the last admin could lock everyone out
+	if actor == id {
+		return ApiResponse {
+			json: json!({"message": "Role change failed: Can't change your own role"}),
+			status: Status::UnprocessableEntity,
+		}
+	}
+	match Admin::set_role(&conn, &actor, &id, &change.role) {
+		Ok(()) => return ApiResponse {
+					json: json!({"message": "Success"}),
+					status: Status::Ok
+				},
+		Err(e) => return ApiResponse {
+					json: json!({"message": String::from("Role change failed: ")+&e.details}),
+					status: Status::UnprocessableEntity,
+				}
+	}
+}
+
+#[post("/admin/catalog/reload", format = "application/json")]
+pub fn admin_reload_catalog(conn: DbConn, catalog: State<LiveCatalog>, key: Result<Bearer, JsonValue>) -> ApiResponse {
+	let actor = match staff(&conn, key, "admin") {
+		Ok(actor) => actor,
+		Err(response) => return response
+	};
+	match catalog.reload() {
+		Ok(catalog) => {
+			let counts = json!({
+				"villagers": catalog.villagers.len(),
+				"recipes": catalog.recipes.len(),
+				"events": catalog.events.len(),
+				"achievements": catalog.achievements.len()
+			});
+			let _ = Admin::audit(&conn, &actor, "reload-catalog", &String::new(), &counts.to_string());
+			return ApiResponse {
+				json: json!({"message": "Success", "catalog": counts}),
+				status: Status::Ok
+			}
+		},
+		Err(e) => {
+			let _ = Admin::audit(&conn, &actor, "reload-catalog", &String::new(), &e.details);
+			return ApiResponse {
+				json: json!({"message": String::from("Catalog reload failed: ")+&e.details}),
+				status: Status::UnprocessableEntity,
+			}
+		}
+	}
+}
+
+#[get("/admin/audit?<count>", format = "application/json")]
+pub fn admin_get_audit(conn: DbConn, key: Result<Bearer, JsonValue>, count: Option<usize>) -> ApiResponse {
+	if let Err(response) = staff(&conn, key, "admin") {
+		return response
+	}
+	match Admin::audit_log(&conn, count) {
+		Ok(entries) => return ApiResponse {
+					json: json!(entries),
+					status: Status::Ok
+				},
+		Err(e) => return ApiResponse {
+					json: json!({"message": String::from("Audit log failed: ")+&e.details}),
+					status: Status::NotFound,
+				}
+	}
+}
\ No newline at end of file
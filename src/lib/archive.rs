@@ -0,0 +1,385 @@
+use crate::{DbConn, DbConnPool};
+use rocket_contrib::databases::redis::{self, Commands, pipe, PipelineCommands, Value};
+use rocket_contrib::json::JsonValue;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::thread;
+use nanoid::nanoid;
+use zip::{CompressionMethod, ZipWriter};
+use zip::write::FileOptions;
+
+use crate::lib::account::Account;
+use crate::lib::achievement::Achievement;
+use crate::lib::admin::Admin;
+use crate::lib::checklist::Checklist;
+use crate::lib::class::Class;
+use crate::lib::history::{History, HistoryQuery};
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::listing::Listing;
+use crate::lib::queue::Session;
+use crate::lib::recipe::RecipeCard;
+use crate::lib::snapshot::Snapshot;
+use crate::lib::subscriber::Subscriber;
+use crate::lib::trade::{now, Trade};
+use crate::lib::turnip::{Gates, TurnipWeek};
+use crate::lib::villager::{Dreamies, Resident};
+use crate::lib::wishlist::Wishlist;
+
+// A ready archive is handed out for a day, then a new one is made
+const FRESH_FOR: u64 = 24 * 60 * 60;
+// A job still pending after this long died with its worker
+const STALE_AFTER: u64 = 30 * 60;
+// The status outlives the archive so clients can tell it expired
+const STATUS_TTL: usize = 7 * 24 * 60 * 60;
+// Everything in the history stream
+const HISTORY_COUNT: usize = 10000;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DataExport {
+	pub id: String,
+	// "pending", "ready" or "failed"
+	pub status: String,
+	pub created: u64,
+	pub finished: Option<u64>,
+	pub size: Option<u64>,
+	pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct ArchiveError {
+	pub details: String
+}
+
+impl ArchiveError {
+	fn new(msg: &str) -> ArchiveError {
+		ArchiveError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for ArchiveError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for ArchiveError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+/*
+ * Archives are kept in EXPORT_DIR, "exports" by default
+ */
+fn export_dir() -> PathBuf {
+	PathBuf::from(env::var("EXPORT_DIR").unwrap_or("exports".to_string()))
+}
+
+/*
+ * The subscriber's scores on the boards and whether they opted out
+ */
+fn leaderboard_entries(redis: &DbConn, subscriber_id: &String, classes: &[String]) -> Result<JsonValue, ArchiveError> {
+	let boards: Vec<String> = std::iter::once(Leaderboard::key(&None))
+		.chain(classes.iter().map(|class| Leaderboard::key(&Some(class.to_owned()))))
+		.collect();
+	let mut query = pipe();
+	for board in &boards {
+		query.zscore(board, subscriber_id);
+	}
+	let scores = match query.query::<Vec<Option<f64>>>(&**redis) {
+		Ok(scores) => scores,
+		Err(_e) => return Err(ArchiveError::new("Scores not found"))
+	};
+	let optout = match redis.sismember::<_, _, bool>(Leaderboard::optout_key(), subscriber_id) {
+		Ok(optout) => optout,
+		Err(_e) => return Err(ArchiveError::new("Scores not found"))
+	};
+	let scores: serde_json::Map<String, serde_json::Value> = boards.into_iter().zip(scores)
+		.filter_map(|(board, score)| score.map(|score| (board, json!(score).into_inner())))
+		.collect();
+	Ok(json!({"scores": scores, "optout": optout}))
+}
+
+/*
+ * Every key under the subscriber's id as it is stored
+ */
+fn raw_keys(redis: &DbConn, subscriber_id: &String) -> Result<JsonValue, ArchiveError> {
+	let mut keys = Account::keys(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+	keys.sort();
+	let mut data = serde_json::Map::new();
+	for key in keys {
+		let kind = match redis::cmd("TYPE").arg(&key).query::<String>(&**redis) {
+			Ok(kind) => kind,
+			Err(_e) => return Err(ArchiveError::new("Keys not found"))
+		};
+		let value = match kind.as_str() {
+			"string" => redis.get::<_, String>(&key).map(|value| json!(value)),
+			"hash" => redis.hgetall::<_, std::collections::BTreeMap<String, String>>(&key).map(|value| json!(value)),
+			"set" => redis.smembers::<_, Vec<String>>(&key).map(|value| json!(value)),
+			"zset" => redis.zrange_withscores::<_, Vec<(String, f64)>>(&key, 0, -1).map(|value| json!(value)),
+			"list" => redis.lrange::<_, Vec<String>>(&key, 0, -1).map(|value| json!(value)),
+			"stream" => redis::cmd("XRANGE").arg(&key).arg("-").arg("+").query::<Value>(&**redis).map(|value| json!(raw_value(&value))),
+			// Expired between the scan and now
+			_ => continue
+		};
+		match value {
+			Ok(value) => { data.insert(key, value.into_inner()); },
+			Err(_e) => return Err(ArchiveError::new("Keys not found"))
+		}
+	}
+	Ok(json!(data))
+}
+
+fn raw_value(value: &Value) -> serde_json::Value {
+	match value {
+		Value::Nil => serde_json::Value::Null,
+		Value::Int(number) => serde_json::Value::from(*number),
+		Value::Data(bytes) => serde_json::Value::from(String::from_utf8_lossy(bytes).into_owned()),
+		Value::Bulk(values) => serde_json::Value::Array(values.iter().map(raw_value).collect()),
+		Value::Status(status) => serde_json::Value::from(status.to_owned()),
+		Value::Okay => serde_json::Value::from("OK")
+	}
+}
+
+impl DataExport {
+
+	fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":data-export".to_string()].concat()
+	}
+
+	pub fn path(subscriber_id: &String, id: &String) -> PathBuf {
+		export_dir().join([subscriber_id.to_owned(), "-".to_string(), id.to_owned(), ".zip".to_string()].concat())
+	}
+
+	/*
+	 * The subscriber's latest export, if there's one
+	 */
+	pub fn status(redis: &DbConn, subscriber_id: &String) -> Result<Option<DataExport>, ArchiveError> {
+		match redis.hgetall::<_, Vec<(String, String)>>(DataExport::key(subscriber_id)) {
+			Ok(value) => {
+				if value.is_empty() {
+					return Ok(None)
+				}
+				let field = |name: &str| value.iter()
+					.find(|(key, _)| key == name)
+					.map(|(_, value)| value.to_owned())
+					.unwrap_or_default();
+				let error = field("error");
+				let mut export = DataExport {
+					id: field("id"),
+					status: field("status"),
+					created: field("created").parse().unwrap_or(0),
+					finished: field("finished").parse().ok(),
+					size: field("size").parse().ok(),
+					error: if error.is_empty() { None } else { Some(error) }
+				};
+				if export.status == "pending" && !export.is_running() {
+					export.status = "failed".to_string();
+					export.error = Some("Export timed out".to_string());
+				}
+				Ok(Some(export))
+			},
+			Err(_e) => Err(ArchiveError::new("Export not found"))
+		}
+	}
+
+	fn save(&self, redis: &DbConn, subscriber_id: &String) -> Result<(), ArchiveError> {
+		let key = DataExport::key(subscriber_id);
+		match pipe()
+				.atomic()
+				.del(&key)
+				.ignore()
+				.hset_multiple(&key, &[
+									("id", self.id.to_owned()),
+									("status", self.status.to_owned()),
+									("created", self.created.to_string()),
+									("finished", self.finished.map_or(String::new(), |finished| finished.to_string())),
+									("size", self.size.map_or(String::new(), |size| size.to_string())),
+									("error", self.error.clone().unwrap_or_default()),
+								])
+				.ignore()
+				.expire(&key, STATUS_TTL)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(ArchiveError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Whether the archive can still be downloaded
+	 */
+	pub fn is_fresh(&self) -> bool {
+		self.status == "ready" && now() < self.created + FRESH_FOR
+	}
+
+	/*
+	 * Whether the job is still being worked on, a worker that
+	 * died without saying so leaves it pending forever
+	 */
+	pub fn is_running(&self) -> bool {
+		self.status == "pending" && now() < self.created + STALE_AFTER
+	}
+
+	/*
+	 * Starts building a new archive in the background unless one
+	 * is on its way or a fresh one is there already
+	 */
+	pub fn request(redis: &DbConn, pool: &DbConnPool, subscriber_id: &String) -> Result<DataExport, ArchiveError> {
+		let previous = DataExport::status(redis, subscriber_id)?;
+		if let Some(ref previous) = previous {
+			if previous.is_running() || previous.is_fresh() {
+				return Ok(previous.clone())
+			}
+		}
+		let export = DataExport {
+			id: nanoid!(12),
+			status: "pending".to_string(),
+			created: now(),
+			finished: None,
+			size: None,
+			error: None
+		};
+		export.save(redis, subscriber_id)?;
+		if let Some(previous) = previous {
+			let _ = fs::remove_file(DataExport::path(subscriber_id, &previous.id));
+		}
+		let mut job = export.clone();
+		// Taken here so a busy pool fails the job right away
+		let worker = match pool.0.get() {
+			Ok(connection) => DbConn(connection),
+			Err(_e) => {
+				job.status = "failed".to_string();
+				job.finished = Some(now());
+				job.error = Some("No database connection".to_string());
+				job.save(redis, subscriber_id)?;
+				return Ok(job)
+			}
+		};
+		let subscriber_id = subscriber_id.to_owned();
+		thread::spawn(move || {
+			let redis = worker;
+			// A panic while building still marks the job as failed
+			let result = match panic::catch_unwind(AssertUnwindSafe(|| DataExport::build(&redis, &subscriber_id, &job.id))) {
+				Ok(result) => result,
+				Err(_e) => Err(ArchiveError::new("Archive couldn't be built"))
+			};
+			job.finished = Some(now());
+			match result {
+				Ok(size) => {
+					job.status = "ready".to_string();
+					job.size = Some(size);
+				},
+				Err(e) => {
+					job.status = "failed".to_string();
+					job.error = Some(e.details);
+				}
+			}
+			let _ = job.save(&redis, &subscriber_id);
+		});
+		Ok(export)
+	}
+
+	/*
+	 * Everything kept about the subscriber, one JSON document per file.
+	 * The documents are the readable view, "keys" holds every key under
+	 * the subscriber's id as it is stored. Entries in shared keys (sign in
+	 * identities, role, suspension, leaderboard scores, queues joined) are
+	 * in "account". Other subscribers' friends lists and the audit log
+	 * aren't theirs and are left out.
+	 */
+	pub(crate) fn collect(redis: &DbConn, subscriber_id: &String) -> Result<Vec<(&'static str, JsonValue)>, ArchiveError> {
+		let mut subscriber = Subscriber { id: Some(subscriber_id.to_owned()), social_id: None, proto: None };
+		let identities = Account::identities(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let friends = subscriber.get_friends(redis).map_err(|e| ArchiveError::new(&e.details))?;
+		let classes = Class::get_all(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let query = HistoryQuery { class: None, item: None, from: None, to: None, count: Some(HISTORY_COUNT) };
+		let history = History::get(redis, subscriber_id, &query).map_err(|e| ArchiveError::new(&e.details))?;
+		let recipes = RecipeCard::get_all(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let wishlist = Wishlist::get(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let trades = Trade::list(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let achievements = Achievement::badges(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let listings = Listing::list_owned(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let weeks = TurnipWeek::list(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let mut prices = Vec::new();
+		for week in weeks {
+			prices.push(TurnipWeek::get(redis, subscriber_id, &week).map_err(|e| ArchiveError::new(&e.details))?);
+		}
+		let villagers = Resident::get_all(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let dreamies = Dreamies::get(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let snapshots = Snapshot::list(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let role = Admin::role(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		let suspension = Admin::suspension(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?;
+		Ok(vec![
+			("subscriber", json!({"id": subscriber_id, "identities": identities})),
+			("profile", json!(subscriber.get_profile(redis).ok())),
+			("friends", json!(friends)),
+			("classes", json!(classes)),
+			("history", json!(history)),
+			("recipes", json!(recipes)),
+			("wishlist", json!(wishlist)),
+			("checklist", json!(Checklist::get(redis, &subscriber).ok())),
+			("trades", json!(trades)),
+			("achievements", json!(achievements)),
+			("listings", json!(listings)),
+			("turnips", json!({"weeks": prices, "gates": Gates::get(redis, subscriber_id)})),
+			("villagers", json!({"residents": villagers, "dreamies": dreamies})),
+			("snapshots", json!(snapshots)),
+			("queues", json!({"hosted": Session::hosted(redis, subscriber_id).ok(), "joined": Session::joined(redis, subscriber_id).map_err(|e| ArchiveError::new(&e.details))?})),
+			("account", json!({
+				"identities": identities,
+				"role": role,
+				"suspension": suspension,
+				"leaderboard": leaderboard_entries(redis, subscriber_id, &classes.keys().cloned().collect::<Vec<String>>())?
+			})),
+			("keys", raw_keys(redis, subscriber_id)?),
+		])
+	}
+
+	/*
+	 * Writes the ZIP with everything kept about the subscriber,
+	 * returns its size in bytes
+	 */
+	fn build(redis: &DbConn, subscriber_id: &String, id: &String) -> Result<u64, ArchiveError> {
+		let files = DataExport::collect(redis, subscriber_id)?;
+		if fs::create_dir_all(export_dir()).is_err() {
+			return Err(ArchiveError::new("Archive couldn't be written"))
+		}
+		let path = DataExport::path(subscriber_id, id);
+		let file = match File::create(&path) {
+			Ok(file) => file,
+			Err(_e) => return Err(ArchiveError::new("Archive couldn't be written"))
+		};
+		let mut zip = ZipWriter::new(file);
+		let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+		for (name, content) in files {
+			let content = serde_json::to_vec_pretty(&*content).unwrap_or_default();
+			if zip.start_file([name, ".json"].concat(), options).is_err() || zip.write_all(&content).is_err() {
+				return Err(ArchiveError::new("Archive couldn't be written"))
+			}
+		}
+		if zip.finish().is_err() {
+			return Err(ArchiveError::new("Archive couldn't be written"))
+		}
+		match fs::metadata(&path) {
+			Ok(metadata) => Ok(metadata.len()),
+			Err(_e) => Err(ArchiveError::new("Archive couldn't be written"))
+		}
+	}
+
+	/*
+	 * Deletes the subscriber's archive from disk
+	 */
+	pub fn remove(redis: &DbConn, subscriber_id: &String) -> Result<(), ArchiveError> {
+		if let Some(export) = DataExport::status(redis, subscriber_id)? {
+			let _ = fs::remove_file(DataExport::path(subscriber_id, &export.id));
+		}
+		Ok(())
+	}
+}
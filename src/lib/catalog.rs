@@ -0,0 +1,159 @@
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::env;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, RwLock};
+
+/*
+ * Game data bundled in the data folder (or DATA_DIR),
+ * it's loaded at launch and again when an admin reloads it
+ */
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Villager {
+	pub id: String,
+	pub name: String,
+	pub species: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Recipe {
+	pub id: String,
+	pub name: String,
+	pub category: String,
+	// Where the card usually comes from, a villager, a balloon, Celeste...
+	pub source: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EventItem {
+	pub class: String,
+	pub id: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Event {
+	pub id: String,
+	pub name: String,
+	// "north", "south" or "both"
+	pub hemisphere: String,
+	// Every year from start to end as MM-DD, it wraps around new year if end is before start
+	pub start: String,
+	pub end: String,
+	pub items: Vec<EventItem>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AchievementRule {
+	pub id: String,
+	pub name: String,
+	pub description: String,
+	// "donated", "class_donated", "trades", "friends", "recipes" or "profile"
+	pub kind: String,
+	// Needed by "class_donated"
+	pub class: Option<String>,
+	// How many it takes, "recipes" without a count means every recipe
+	pub count: Option<u32>,
+}
+
+pub struct Catalog {
+	pub villagers: Vec<Villager>,
+	pub recipes: Vec<Recipe>,
+	pub events: Vec<Event>,
+	pub achievements: Vec<AchievementRule>,
+}
+
+/*
+ * The catalog the API serves, requests hold on to the one
+ * they started with while a reload swaps it
+ */
+pub struct LiveCatalog(RwLock<Arc<Catalog>>);
+
+#[derive(Debug)]
+pub struct CatalogError {
+	pub details: String
+}
+
+impl CatalogError {
+	fn new(msg: &str) -> CatalogError {
+		CatalogError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for CatalogError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for CatalogError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+fn read<T: DeserializeOwned>(file: &str) -> Result<T, CatalogError> {
+	let mut path = PathBuf::from(env::var("DATA_DIR").unwrap_or("data".to_string()));
+	path.push(file);
+	let content = match fs::read_to_string(&path) {
+		Ok(content) => content,
+		Err(e) => return Err(CatalogError::new(&[file.to_string(), ": ".to_string(), e.to_string()].concat()))
+	};
+	match serde_json::from_str::<T>(&content) {
+		Ok(value) => Ok(value),
+		Err(e) => Err(CatalogError::new(&[file.to_string(), ": ".to_string(), e.to_string()].concat()))
+	}
+}
+
+impl Catalog {
+
+	/*
+	 * Reads every data file
+	 */
+	pub fn load() -> Result<Catalog, CatalogError> {
+		Ok(Catalog {
+			villagers: read("villagers.json")?,
+			recipes: read("recipes.json")?,
+			events: read("events.json")?,
+			achievements: read("achievements.json")?,
+		})
+	}
+
+	pub fn villager(&self, id: &String) -> Option<&Villager> {
+		self.villagers.iter().find(|villager| &villager.id == id)
+	}
+
+	pub fn recipe(&self, id: &String) -> Option<&Recipe> {
+		self.recipes.iter().find(|recipe| &recipe.id == id)
+	}
+}
+
+impl LiveCatalog {
+
+	pub fn new(catalog: Catalog) -> LiveCatalog {
+		LiveCatalog(RwLock::new(Arc::new(catalog)))
+	}
+
+	pub fn current(&self) -> Arc<Catalog> {
+		match self.0.read() {
+			Ok(catalog) => catalog.clone(),
+			Err(poisoned) => poisoned.into_inner().clone()
+		}
+	}
+
+	/*
+	 * Reads every data file again, the old catalog stays
+	 * if any of them is broken
+	 */
+	pub fn reload(&self) -> Result<Arc<Catalog>, CatalogError> {
+		let catalog = Arc::new(Catalog::load()?);
+		match self.0.write() {
+			Ok(mut current) => *current = catalog.clone(),
+			Err(poisoned) => *poisoned.into_inner() = catalog.clone()
+		}
+		Ok(catalog)
+	}
+}
@@ -0,0 +1,272 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::lib::subscriber::Subscriber;
+
+// The game day starts at 5 AM island time
+const RESET_HOUR: i64 = 5;
+const MAX_TASKS: usize = 30;
+
+pub const DEFAULT_TASKS: &[(&str, &str)] = &[
+	("rocks", "Hit the rocks"),
+	("fossils", "Dig up fossils"),
+	("message_bottle", "Check the beach for a message bottle"),
+	("money_rock", "Find the money rock"),
+	("shops", "Check the shops"),
+	("villagers", "Talk to the villagers"),
+	("celeste", "Look for Celeste"),
+];
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Task {
+	pub id: String,
+	pub name: String,
+	#[serde(default)]
+	pub done: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Checklist {
+	// Game day as YYYY-MM-DD
+	pub day: String,
+	// When the day is over, unix seconds
+	pub resets: i64,
+	pub tasks: Vec<Task>,
+	// Days in a row with every task done
+	pub streak: u32,
+	pub best: u32,
+}
+
+#[derive(Debug)]
+pub struct ChecklistError {
+	pub details: String
+}
+
+impl ChecklistError {
+	fn new(msg: &str) -> ChecklistError {
+		ChecklistError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for ChecklistError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for ChecklistError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+/*
+ * The game day the island is in and when it ends
+ */
+fn game_day(timezone: &Tz) -> (NaiveDate, i64) {
+	let local = Utc::now().with_timezone(timezone).naive_local();
+	let day = (local - Duration::hours(RESET_HOUR)).date();
+	let next = (day + Duration::days(1)).and_hms(RESET_HOUR as u32, 0, 0);
+	let resets = match timezone.from_local_datetime(&next).earliest() {
+		Some(resets) => resets.timestamp(),
+		// 5 AM skipped by a DST change, the day ends an hour later
+		None => timezone.from_local_datetime(&(next + Duration::hours(1))).earliest()
+				.map_or(Utc::now().timestamp() + 24 * 60 * 60, |resets| resets.timestamp())
+	};
+	(day, resets)
+}
+
+/*
+ * Current and best run of consecutive days, today only counts once it's complete
+ */
+fn streaks(days: &Vec<String>, today: NaiveDate) -> (u32, u32) {
+	let mut dates: Vec<NaiveDate> = days.iter()
+		.filter_map(|day| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok())
+		.collect();
+	dates.sort();
+	dates.dedup();
+	let (mut best, mut run) = (0, 0);
+	let mut last: Option<NaiveDate> = None;
+	for date in &dates {
+		run = match last {
+			Some(last) if *date - last == Duration::days(1) => run + 1,
+			_ => 1
+		};
+		best = best.max(run);
+		last = Some(*date);
+	}
+	let current = match last {
+		Some(last) if last == today || last == today - Duration::days(1) => run,
+		_ => 0
+	};
+	(current, best)
+}
+
+impl Checklist {
+
+	fn tasks_key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":checklist".to_string()].concat()
+	}
+
+	fn done_key(subscriber_id: &String, day: &NaiveDate) -> String {
+		[subscriber_id.to_owned(), ":checklist:".to_string(), day.format("%Y-%m-%d").to_string()].concat()
+	}
+
+	fn days_key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":checklist:days".to_string()].concat()
+	}
+
+	/*
+	 * The subscriber's tasks, the default ones until they customize them
+	 */
+	fn tasks(redis: &DbConn, subscriber_id: &String) -> Result<Vec<Task>, ChecklistError> {
+		match redis.get::<_, Option<String>>(Checklist::tasks_key(subscriber_id)) {
+			Ok(Some(value)) => match serde_json::from_str::<Vec<Task>>(&value) {
+				Ok(tasks) => Ok(tasks),
+				Err(_e) => Err(ChecklistError::new("Invalid tasks"))
+			},
+			Ok(None) => Ok(DEFAULT_TASKS.iter()
+							.map(|(id, name)| Task { id: id.to_string(), name: name.to_string(), done: false })
+							.collect()),
+			Err(_e) => Err(ChecklistError::new("Checklist not found"))
+		}
+	}
+
+	/*
+	 * Gets today's checklist on the subscriber's island
+	 */
+	pub fn get(redis: &DbConn, subscriber: &Subscriber) -> Result<Checklist, ChecklistError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(ChecklistError::new("No Id"))
+		};
+		let (day, resets) = game_day(&subscriber.get_timezone(redis));
+		let mut tasks = Checklist::tasks(redis, &subscriber_id)?;
+		let (done, days) = match pipe()
+				.smembers(Checklist::done_key(&subscriber_id, &day))
+				.zrange(Checklist::days_key(&subscriber_id), 0, -1)
+				.query::<(Vec<String>, Vec<String>)>(&**redis) {
+			Ok(value) => value,
+			Err(_e) => return Err(ChecklistError::new("Checklist not found"))
+		};
+		for task in tasks.iter_mut() {
+			task.done = done.contains(&task.id);
+		}
+		let (streak, best) = streaks(&days, day);
+		Ok(Checklist {
+			day: day.format("%Y-%m-%d").to_string(),
+			resets: resets,
+			tasks: tasks,
+			streak: streak,
+			best: best
+		})
+	}
+
+	/*
+	 * Keeps the completed days in sync with the checklist
+	 */
+	fn record_day(redis: &DbConn, subscriber_id: &String, checklist: &Checklist) -> Result<(), ChecklistError> {
+		let key = Checklist::days_key(subscriber_id);
+		let complete = !checklist.tasks.is_empty() && checklist.tasks.iter().all(|task| task.done);
+		let result = if complete {
+			redis.zadd::<_,_,_,()>(key, &checklist.day, checklist.resets)
+		} else {
+			redis.zrem::<_,_,()>(key, &checklist.day)
+		};
+		match result {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(ChecklistError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Marks a task as done or not done for today
+	 */
+	pub fn toggle(redis: &DbConn, subscriber: &Subscriber, task_id: &String) -> Result<Checklist, ChecklistError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(ChecklistError::new("No Id"))
+		};
+		let mut checklist = Checklist::get(redis, subscriber)?;
+		let was_done = match checklist.tasks.iter_mut().find(|task| &task.id == task_id) {
+			Some(task) => {
+				task.done = !task.done;
+				!task.done
+			},
+			None => return Err(ChecklistError::new("Task not found"))
+		};
+		let day = NaiveDate::parse_from_str(&checklist.day, "%Y-%m-%d").unwrap();
+		let key = Checklist::done_key(&subscriber_id, &day);
+		let mut query = pipe();
+		if was_done {
+			query.srem(&key, task_id).ignore();
+		} else {
+			query.sadd(&key, task_id).ignore();
+		}
+		// A day of grace so the last day is still around for the streak
+		match query
+				.expire_at(&key, (checklist.resets + 24 * 60 * 60) as usize)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => {},
+			Err(_e) => return Err(ChecklistError::new("unknown error"))
+		}
+		Checklist::record_day(redis, &subscriber_id, &checklist)?;
+		Checklist::get(redis, subscriber)
+	}
+
+	/*
+	 * Replaces the subscriber's tasks, today's progress is kept for the ones still there
+	 */
+	pub fn set_tasks(redis: &DbConn, subscriber: &Subscriber, tasks: Vec<Task>) -> Result<Checklist, ChecklistError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(ChecklistError::new("No Id"))
+		};
+		if tasks.is_empty() || tasks.len() > MAX_TASKS {
+			return Err(ChecklistError::new("Invalid number of tasks"))
+		}
+		let mut ids: Vec<&String> = Vec::new();
+		for task in &tasks {
+			if task.id.is_empty() || task.name.is_empty()
+				|| !task.id.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+				return Err(ChecklistError::new("Invalid task"))
+			}
+			if ids.contains(&&task.id) {
+				return Err(ChecklistError::new("Duplicated task"))
+			}
+			ids.push(&task.id);
+		}
+		let tasks: Vec<Task> = tasks.iter()
+			.map(|task| Task { id: task.id.to_owned(), name: task.name.to_owned(), done: false })
+			.collect();
+		let value = serde_json::to_string(&tasks).unwrap();
+		if redis.set::<_,_,()>(Checklist::tasks_key(&subscriber_id), value).is_err() {
+			return Err(ChecklistError::new("unknown error"))
+		}
+		let checklist = Checklist::get(redis, subscriber)?;
+		Checklist::record_day(redis, &subscriber_id, &checklist)?;
+		Checklist::get(redis, subscriber)
+	}
+
+	/*
+	 * Goes back to the default tasks
+	 */
+	pub fn reset_tasks(redis: &DbConn, subscriber: &Subscriber) -> Result<Checklist, ChecklistError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(ChecklistError::new("No Id"))
+		};
+		if redis.del::<_,()>(Checklist::tasks_key(&subscriber_id)).is_err() {
+			return Err(ChecklistError::new("unknown error"))
+		}
+		let checklist = Checklist::get(redis, subscriber)?;
+		Checklist::record_day(redis, &subscriber_id, &checklist)?;
+		Checklist::get(redis, subscriber)
+	}
+}
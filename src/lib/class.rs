@@ -1,6 +1,14 @@
 use crate::DbConn;  
-use rocket_contrib::databases::redis::{ Commands};
+use crate::lib::subscriber::{Subscriber, Friend};
+use crate::lib::wishlist::Wishlist;
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::history::History;
+use crate::lib::snapshot::{self, Snapshot};
+use crate::lib::sync::Revision;
+use crate::lib::etag;
+use rocket_contrib::databases::redis::{self, Commands, Pipeline, pipe, PipelineCommands};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::error;
 use std::fmt;
 
@@ -15,6 +23,24 @@ pub struct Item {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Class(pub String);
 
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Holder {
+	pub friend: Friend,
+	pub donated: bool,
+	pub stock: u32,
+}
+
+/* 
+ * Key suffixes that live next to the class hashes
+ * and can't be used as class names
+ */
+pub const RESERVED: &[&str] = &[
+	"profile", "friends", "trades", "wishlist", "listings",
+	"turnips", "gates", "queue", "villagers", "dreamies", "recipes",
+	"checklist", "achievements", "history", "snapshots", "revisions",
+	"classes",
+];
+
 #[derive(Debug)]
 pub struct ItemError {
 	pub details: String
@@ -24,6 +50,10 @@ impl ItemError {
 	fn new(msg: &str) -> ItemError {
 		ItemError{details: msg.to_string()}
 	}
+
+	pub fn is_changed(&self) -> bool {
+		self.details == etag::CHANGED
+	}
 }
 
 impl fmt::Display for ItemError {
@@ -38,10 +68,184 @@ impl error::Error for ItemError {
 	}
 }
 
+impl Item {
+	/*
+	 * Builds an item from the "donated:stock" value we keep in redis
+	 */
+	pub fn from_meta(class: &String, id: &String, meta: &str) -> Option<Item> {
+		let meta: Vec<&str> = meta.split(":").collect();
+		if meta.len() != 2 {
+			return None
+		}
+		match (meta[0].parse::<bool>(), meta[1].parse::<u32>()) {
+			(Ok(donated), Ok(stock)) => Some(Item {
+											class: class.to_owned(),
+											id: id.to_owned(),
+											donated: donated,
+											stock: stock,
+										}),
+			_ => None
+		}
+	}
+
+	/*
+	 * How many of this item can be given away, we keep
+	 * one around if it still has to be donated
+	 */
+	pub fn spare(&self) -> u32 {
+		if self.donated {
+			self.stock
+		} else {
+			self.stock.saturating_sub(1)
+		}
+	}
+
+	/*
+	 * The item is still missing from the museum
+	 */
+	pub fn needed(&self) -> bool {
+		!self.donated
+	}
+}
+
 impl Class {
+	/*
+	 * Class names can't clash with the other subscriber keys
+	 */
+	pub fn is_valid(&self) -> bool {
+		!self.0.is_empty() && !RESERVED.contains(&self.0.as_str()) && !self.0.contains(":")
+	}
+
+	/*
+	 * Lists the classes a subscriber has stored
+	 */
+	pub(crate) fn index_key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":classes".to_string()].concat()
+	}
+
+	pub(crate) fn indexed_key() -> &'static str {
+		"classes:indexed"
+	}
+
+	/*
+	 * Queues adding a class to the subscriber's class index
+	 */
+	pub(crate) fn index(query: &mut Pipeline, subscriber_id: &String, class: &String) {
+		query.sadd(Class::index_key(subscriber_id), class).ignore();
+	}
+
+	/*
+	 * The classes a subscriber has stored, subscribers from
+	 * before the index was kept get their keys scanned once
+	 */
+	pub fn list_classes(redis: &DbConn, subscriber_id: &String) -> Result<Vec<String>, ItemError> {
+		match redis.sismember::<_,_,bool>(Class::indexed_key(), subscriber_id) {
+			Ok(true) => {},
+			Ok(false) => return Class::build_index(redis, subscriber_id),
+			Err(_e) => return Err(ItemError::new("Classes not found"))
+		}
+		match redis.smembers::<_, Vec<String>>(Class::index_key(subscriber_id)) {
+			Ok(mut classes) => {
+				classes.sort();
+				Ok(classes)
+			},
+			Err(_e) => Err(ItemError::new("Classes not found"))
+		}
+	}
+
+	/*
+	 * The classes of several subscribers, their indexes are read in a single pipeline
+	 */
+	pub(crate) fn list_classes_of(redis: &DbConn, subscriber_ids: &[String]) -> Result<Vec<Vec<String>>, ItemError> {
+		if subscriber_ids.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for subscriber_id in subscriber_ids {
+			query.sismember(Class::indexed_key(), subscriber_id)
+				.smembers(Class::index_key(subscriber_id));
+		}
+		let values = match query.query::<Vec<(bool, Vec<String>)>>(&**redis) {
+			Ok(values) => values,
+			Err(_e) => return Err(ItemError::new("Classes not found"))
+		};
+		let mut all: Vec<Vec<String>> = Vec::new();
+		for (subscriber_id, (indexed, mut classes)) in subscriber_ids.iter().zip(values) {
+			if !indexed {
+				classes = Class::build_index(redis, subscriber_id)?;
+			}
+			classes.sort();
+			all.push(classes);
+		}
+		Ok(all)
+	}
+
+	fn build_index(redis: &DbConn, subscriber_id: &String) -> Result<Vec<String>, ItemError> {
+		let classes = Class::scan_classes(redis, subscriber_id)?;
+		let mut query = pipe();
+		query.atomic();
+		for class in &classes {
+			Class::index(&mut query, subscriber_id, class);
+		}
+		match query
+				.sadd(Class::indexed_key(), subscriber_id)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => Ok(classes),
+			Err(_e) => Err(ItemError::new("Classes not found"))
+		}
+	}
+
+	fn scan_classes(redis: &DbConn, subscriber_id: &String) -> Result<Vec<String>, ItemError> {
+		let pattern = &[
+						subscriber_id.to_owned(),
+						":*".to_string()
+					].concat();
+		match redis.scan_match::<_, String>(pattern) {
+			Ok(keys) => {
+				let mut classes: Vec<String> = Vec::new();
+				for key in keys {
+					let name = key[subscriber_id.len() + 1..].to_string();
+					if !RESERVED.contains(&name.as_str()) && !name.contains(":") {
+						classes.push(name);
+					}
+				}
+				classes.sort();
+				return Ok(classes)
+			},
+			Err(_e) => Err(ItemError::new("Classes not found"))
+		}
+	}
+
+	/*
+	 * Every class of a subscriber, read in a single pipeline
+	 */
+	pub fn get_all(redis: &DbConn, subscriber_id: &String) -> Result<HashMap<String, Vec<Item>>, ItemError> {
+		let classes = Class::list_classes(redis, subscriber_id)?;
+		if classes.is_empty() {
+			return Ok(HashMap::new())
+		}
+		let mut query = pipe();
+		for class in &classes {
+			query.hgetall([subscriber_id.to_owned(), ":".to_string(), class.to_owned()].concat());
+		}
+		match query.query::<Vec<Vec<(String, String)>>>(&**redis) {
+			Ok(values) => Ok(classes.into_iter()
+								.zip(values)
+								.map(|(class, items)| {
+									let items = items.iter()
+										.filter_map(|(id, meta)| Item::from_meta(&class, id, meta))
+										.collect();
+									(class, items)
+								})
+								.collect()),
+			Err(_e) => Err(ItemError::new("Items not found"))
+		}
+	}
+
 	pub fn get_class(
 						&mut self, 
-						redis:DbConn, 
+						redis: &DbConn, 
 						subscriber_id: &String, 
 					) -> Result<Vec<Item>, ItemError> {
 		let query = &[
@@ -54,19 +258,10 @@ impl Class {
 								Vec<(String, String)>
 							>(query) {
 				Ok(value) => {
-					let mut items: Vec<Item> = Vec::new();
-					for val in value {
-						let meta:Vec<&str> = val.1.split(":").collect();
-						let donated: bool = meta[0].parse().unwrap();
-						let stock: u32 = meta[1].parse().unwrap();
-						let obj = Item {
-											class: self.0.to_owned(),
-											id:val.0.clone(),
-											donated: donated,
-											stock: stock,
-										};
-						items.push(obj);
-					}
+					// Malformed values are left for the admin tool to repair
+					let items: Vec<Item> = value.iter()
+						.filter_map(|(id, meta)| Item::from_meta(&self.0, id, meta))
+						.collect();
 					return Ok(items)
 
 				},
@@ -74,18 +269,24 @@ impl Class {
 		}
 	}
 
+	/*
+	 * Writes the items, if the If-Match ETag is given the class
+	 * revision is checked in the same transaction. Returns the new revision
+	 */
 	pub fn set_class(
 					 &mut self,
-					 redis:DbConn,
+					 redis: &DbConn,
 					 subscriber_id: &String,
-					 items: Vec<Item>
-					) -> Result<(), ItemError>{
+					 items: Vec<Item>,
+					 client: &String,
+					 if_match: Option<&String>
+					) -> Result<u64, ItemError>{
 
 		/* I think this would fail anyways but let's 
 		 * avoid the user overwriting their friends list
 		 * or profile
 		*/
-		if self.0 == "profile" || self.0 == "friends" {
+		if !self.is_valid() {
 			return Err(ItemError::new("Invalid class"))
 		}
 		let query = &[
@@ -95,7 +296,7 @@ impl Class {
 					].concat();
 		// We need to process the Items into something simple for redis
 		let mut items_pr = Vec::new();
-		for item in items {
+		for item in &items {
 			let meta = [item.donated.to_string(), 
 						 ":".to_string(), 
 						 item.stock.to_string()
@@ -103,17 +304,195 @@ impl Class {
 			let obj = (item.id.to_owned(), meta);
 			items_pr.push(obj);
 		}
-		if !items_pr.is_empty() {
-			match redis.hset_multiple::<_, _, _, String>(query, &items_pr){
-				Ok(_) => {
-					return Ok(());
-				},
-				Err(_e) => Err(ItemError::new("Adding items failed"))
-			
+		if items_pr.is_empty() {
+			let revision = match Revision::get(redis, subscriber_id, &self.0) {
+				Ok(revision) => revision,
+				Err(_e) => return Err(ItemError::new("Adding items failed"))
+			};
+			return match if_match {
+				Some(tags) if !etag::matches(tags, &etag::revision(revision)) => Err(ItemError::new(etag::CHANGED)),
+				_ => Ok(revision)
 			}
-		} else {
-			return Ok(());
+		}
+		// Bad syncs have wiped inventories, big writes can be undone
+		if items.len() >= snapshot::BULK_ITEMS {
+			let reason = ["a bulk write to ".to_string(), self.0.to_owned()].concat();
+			if let Err(e) = Snapshot::auto(redis, subscriber_id, &reason) {
+				return Err(ItemError::new(&e.details))
+			}
+		}
+		if let Err(e) = Leaderboard::ensure_counted(redis, subscriber_id) {
+			return Err(ItemError::new(&e.details))
+		}
+		// The last value of an item wins, same as in the hash
+		let mut latest: HashMap<&String, &Item> = HashMap::new();
+		for item in &items {
+			latest.insert(&item.id, item);
+		}
+		let ids: Vec<&String> = latest.keys().cloned().collect();
+		let keys = [query.to_owned(), Revision::key(subscriber_id)];
+		let mut changed_since = false;
+		let mut revision: u64 = 0;
+		let result = redis::transaction(&**redis, &keys, |pipe| {
+			changed_since = false;
+			if let Some(tags) = if_match {
+				if !etag::matches(tags, &etag::revision(Revision::get(redis, subscriber_id, &self.0)?)) {
+					changed_since = true;
+					return Ok(Some(()))
+				}
+			}
+			let current = redis::cmd("HMGET").arg(query).arg(&ids).query::<Vec<Option<String>>>(&**redis)?;
+			let mut delta: i64 = 0;
+			let mut changed: Vec<&String> = Vec::new();
+			for (id, meta) in ids.iter().zip(current) {
+				let old = meta.and_then(|meta| Item::from_meta(&self.0, id, &meta));
+				// The class in the body may not match the one being set
+				let new = Item { class: self.0.to_owned(), ..latest[id].clone() };
+				// Donated flags that changed move the leaderboards
+				match (old.as_ref().map_or(false, |old| old.donated), new.donated) {
+					(false, true) => delta += 1,
+					(true, false) => delta -= 1,
+					_ => {}
+				}
+				if old.as_ref().map_or(true, |old| old.donated != new.donated || old.stock != new.stock) {
+					History::record(pipe, subscriber_id, &old, &new, client);
+					changed.push(*id);
+				}
+			}
+			pipe.hset_multiple(query, &items_pr).ignore();
+			Class::index(pipe, subscriber_id, &self.0);
+			Leaderboard::record(pipe, subscriber_id, &self.0, delta);
+			revision = Revision::record(redis, pipe, subscriber_id, &self.0, &changed)?;
+			pipe.query(&**redis)
+		});
+		match result {
+			Ok(()) if changed_since => Err(ItemError::new(etag::CHANGED)),
+			Ok(()) => {
+				// Anything that arrived in stock leaves the wishlist
+				match Wishlist::fulfil(redis, subscriber_id, &self.0, &items) {
+					Ok(()) => return Ok(revision),
+					Err(e) => Err(ItemError::new(&e.details))
+				}
+			},
+			Err(_e) => Err(ItemError::new("Adding items failed"))
 		}
 	}
 
-}
+	/*
+	 * Rewrites stored values that don't parse, (item, old, new) with
+	 * a None new removing the item. It goes through the same history,
+	 * revision and leaderboard bookkeeping as set_class. Values that
+	 * changed since they were read are left alone, returns the items written
+	 */
+	pub(crate) fn repair(
+					&self,
+					redis: &DbConn,
+					subscriber_id: &String,
+					fixes: &[(String, String, Option<String>)],
+					client: &String
+				) -> Result<Vec<String>, ItemError> {
+		if fixes.is_empty() {
+			return Ok(Vec::new())
+		}
+		if let Err(e) = Leaderboard::ensure_counted(redis, subscriber_id) {
+			return Err(ItemError::new(&e.details))
+		}
+		let query = &[subscriber_id.to_owned(), ":".to_string(), self.0.to_owned()].concat();
+		let ids: Vec<&String> = fixes.iter().map(|(item, _, _)| item).collect();
+		let keys = [query.to_owned(), Revision::key(subscriber_id)];
+		let mut written: Vec<String> = Vec::new();
+		let result = redis::transaction(&**redis, &keys, |pipe| {
+			written.clear();
+			let current = redis::cmd("HMGET").arg(query).arg(&ids).query::<Vec<Option<String>>>(&**redis)?;
+			let mut delta: i64 = 0;
+			let mut changed: Vec<&String> = Vec::new();
+			for ((item, old, new), meta) in fixes.iter().zip(current) {
+				if meta.as_ref() != Some(old) {
+					continue
+				}
+				match new {
+					Some(ref meta) => pipe.hset(query, item, meta).ignore(),
+					None => pipe.hdel(query, item).ignore()
+				};
+				// The broken value never counted, so it's read as nothing
+				let new = new.as_ref()
+					.and_then(|meta| Item::from_meta(&self.0, item, meta))
+					.unwrap_or(Item {
+						class: self.0.to_owned(),
+						id: item.to_owned(),
+						donated: false,
+						stock: 0,
+					});
+				if new.donated {
+					delta += 1;
+				}
+				History::record(pipe, subscriber_id, &None, &new, client);
+				changed.push(item);
+				written.push(item.to_owned());
+			}
+			if changed.is_empty() {
+				return Ok(Some(()))
+			}
+			Leaderboard::record(pipe, subscriber_id, &self.0, delta);
+			Revision::record(redis, pipe, subscriber_id, &self.0, &changed)?;
+			pipe.query(&**redis)
+		});
+		match result {
+			Ok(()) => Ok(written),
+			Err(_e) => Err(ItemError::new("Repairing items failed"))
+		}
+	}
+
+	/*
+	 * Finds which friends have an item of this class in stock,
+	 * friends hiding their inventory are skipped
+	 */
+	pub fn who_has(
+					&mut self,
+					redis: &DbConn,
+					subscriber: &mut Subscriber,
+					item_id: &String
+				  ) -> Result<Vec<Holder>, ItemError> {
+		let friends = match subscriber.get_friends(redis) {
+			Ok(friends) => friends,
+			Err(e) => return Err(ItemError::new(&e.details))
+		};
+		let friends = match subscriber.visible_friends(redis, friends) {
+			Ok(friends) => friends,
+			Err(e) => return Err(ItemError::new(&e.details))
+		};
+		if friends.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for friend in &friends {
+			query.hget([
+						friend.id.to_owned(),
+						":".to_string(), 
+						self.0.to_owned()
+					].concat(), item_id);
+		}
+		match query.query::<Vec<Option<String>>>(&**redis) {
+			Ok(value) => {
+				let mut holders: Vec<Holder> = Vec::new();
+				for (friend, meta) in friends.into_iter().zip(value) {
+					let item = match meta.and_then(|meta| Item::from_meta(&self.0, item_id, &meta)) {
+						Some(item) => item,
+						None => continue
+					};
+					if item.stock > 0 {
+						holders.push(Holder {
+										friend: friend,
+										donated: item.donated,
+										stock: item.stock,
+									});
+					}
+				}
+				holders.sort_by(|a, b| b.stock.cmp(&a.stock));
+				return Ok(holders)
+			},
+			Err(_e) => Err(ItemError::new("Items not found"))
+		}
+	}
+
+}
\ No newline at end of file
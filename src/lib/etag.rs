@@ -0,0 +1,35 @@
+use rocket_contrib::json::JsonValue;
+
+// What writes fail with when the If-Match ETag isn't the current one
+pub const CHANGED: &str = "Resource has changed";
+
+// 64 bit FNV-1a, std's hashers are allowed to change between releases
+const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/*
+ * Classes are tagged with their revision, every write moves it on
+ */
+pub fn revision(revision: u64) -> String {
+	format!("\"r{}\"", revision)
+}
+
+/*
+ * Everything else is tagged with a hash of the body, it has to come
+ * out the same after a rebuild or every client gets a 412
+ */
+pub fn body(json: &JsonValue) -> String {
+	let hash = json.to_string().bytes().fold(FNV_OFFSET, |hash, byte| {
+		(hash ^ byte as u64).wrapping_mul(FNV_PRIME)
+	});
+	format!("\"{:016x}\"", hash)
+}
+
+/*
+ * Whether a list of ETags from a header has this one, weak ones compare the same
+ */
+pub fn matches(tags: &String, etag: &String) -> bool {
+	tags.split(',')
+		.map(|tag| tag.trim())
+		.any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag)
+}
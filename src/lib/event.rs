@@ -0,0 +1,200 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+
+use crate::lib::catalog::{Catalog, EventItem};
+use crate::lib::class::Item;
+use crate::lib::recipe::RecipeCard;
+use crate::lib::subscriber::{Subscriber, HEMISPHERES};
+
+// Searches without an end look a month ahead and can't span more than two years
+const DEFAULT_DAYS: i64 = 30;
+const MAX_DAYS: i64 = 2 * 366;
+
+#[derive(FromForm)]
+pub struct EventQuery {
+	pub hemisphere: Option<String>,
+	// Dates as YYYY-MM-DD, today and a month from it by default
+	pub from: Option<String>,
+	pub to: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EventDates {
+	pub id: String,
+	pub name: String,
+	pub hemisphere: String,
+	pub start: String,
+	pub end: String,
+	pub items: Vec<EventItem>,
+}
+
+#[derive(Debug)]
+pub struct EventError {
+	pub details: String
+}
+
+impl EventError {
+	fn new(msg: &str) -> EventError {
+		EventError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for EventError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for EventError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+fn month_day(year: i32, value: &String) -> Option<NaiveDate> {
+	NaiveDate::parse_from_str(&[year.to_string(), "-".to_string(), value.to_owned()].concat(), "%Y-%m-%d").ok()
+}
+
+fn parse_date(value: &Option<String>, default: NaiveDate) -> Result<NaiveDate, EventError> {
+	match value {
+		Some(value) => match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+			Ok(date) => Ok(date),
+			Err(_e) => Err(EventError::new("Invalid date (YYYY-MM-DD)"))
+		},
+		None => Ok(default)
+	}
+}
+
+impl EventDates {
+
+	/*
+	 * Events happening between two dates, with the dates of each year they happen
+	 */
+	fn between(catalog: &Catalog, hemisphere: &String, from: NaiveDate, to: NaiveDate) -> Vec<EventDates> {
+		let mut events: Vec<EventDates> = Vec::new();
+		for event in &catalog.events {
+			if event.hemisphere != "both" && &event.hemisphere != hemisphere {
+				continue;
+			}
+			// Last year's run can wrap into this one
+			for year in from.year() - 1..to.year() + 1 {
+				let start = match month_day(year, &event.start) {
+					Some(start) => start,
+					None => continue
+				};
+				let end = match month_day(year, &event.end) {
+					Some(end) if end >= start => end,
+					_ => match month_day(year + 1, &event.end) {
+						Some(end) => end,
+						None => continue
+					}
+				};
+				if start <= to && end >= from {
+					events.push(EventDates {
+									id: event.id.to_owned(),
+									name: event.name.to_owned(),
+									hemisphere: event.hemisphere.to_owned(),
+									start: start.format("%Y-%m-%d").to_string(),
+									end: end.format("%Y-%m-%d").to_string(),
+									items: event.items.clone()
+								});
+				}
+			}
+		}
+		events.sort_by(|a, b| a.start.cmp(&b.start));
+		events
+	}
+
+	/*
+	 * Searches the calendar, the hemisphere is north unless told otherwise
+	 */
+	pub fn search(catalog: &Catalog, query: &EventQuery, today: NaiveDate) -> Result<Vec<EventDates>, EventError> {
+		let hemisphere = query.hemisphere.clone().unwrap_or("north".to_string());
+		if !HEMISPHERES.contains(&hemisphere.as_str()) {
+			return Err(EventError::new("Invalid hemisphere"))
+		}
+		let from = parse_date(&query.from, today)?;
+		let to = parse_date(&query.to, from + Duration::days(DEFAULT_DAYS))?;
+		if to < from || to - from > Duration::days(MAX_DAYS) {
+			return Err(EventError::new("Invalid date range"))
+		}
+		Ok(EventDates::between(catalog, &hemisphere, from, to))
+	}
+
+	/*
+	 * Searches the calendar from the subscriber's island, the hemisphere and
+	 * today come from the profile when the query doesn't have them
+	 */
+	pub fn for_subscriber(
+						redis: &DbConn,
+						subscriber: &Subscriber,
+						catalog: &Catalog,
+						query: &mut EventQuery
+					) -> Result<Vec<EventDates>, EventError> {
+		if query.hemisphere.is_none() {
+			query.hemisphere = Some(subscriber.get_hemisphere(redis));
+		}
+		let today = Utc::now().with_timezone(&subscriber.get_timezone(redis)).date().naive_local();
+		EventDates::search(catalog, query, today)
+	}
+
+	/*
+	 * Events on the subscriber's calendar keeping only the items they still
+	 * need, recipes are needed until learned and the rest until there's
+	 * one in stock or donated
+	 */
+	pub fn needed(
+					redis: &DbConn,
+					subscriber: &Subscriber,
+					catalog: &Catalog,
+					query: &mut EventQuery
+				) -> Result<Vec<EventDates>, EventError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(EventError::new("No Id"))
+		};
+		let events = EventDates::for_subscriber(redis, subscriber, catalog, query)?;
+		let mut lookup = pipe();
+		let mut count = 0;
+		for event in &events {
+			for item in &event.items {
+				lookup.hget([subscriber_id.to_owned(), ":".to_string(), item.class.to_owned()].concat(), &item.id);
+				count += 1;
+			}
+		}
+		if count == 0 {
+			return Ok(Vec::new())
+		}
+		let mut values = match lookup.query::<Vec<Option<String>>>(&**redis) {
+			Ok(values) => values.into_iter(),
+			Err(_e) => return Err(EventError::new("Items not found"))
+		};
+		let mut needed: Vec<EventDates> = Vec::new();
+		for mut event in events {
+			let mut items: Vec<EventItem> = Vec::new();
+			for item in event.items {
+				let meta = values.next().unwrap_or(None);
+				let missing = match meta {
+					Some(ref meta) if item.class == "recipes" => {
+						RecipeCard::from_meta(&item.id, meta).map_or(true, |card| !card.learned)
+					},
+					// Not donated and none in storage either
+					Some(ref meta) => Item::from_meta(&item.class, &item.id, meta).map_or(true, |found| found.needed() && found.stock == 0),
+					None => true
+				};
+				if missing {
+					items.push(item);
+				}
+			}
+			if !items.is_empty() {
+				event.items = items;
+				needed.push(event);
+			}
+		}
+		Ok(needed)
+	}
+}
@@ -0,0 +1,197 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{self, Pipeline, Value};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+
+use crate::lib::class::Item;
+use crate::lib::trade::now;
+
+// Streams are trimmed around this size, older entries go first
+const MAX_ENTRIES: usize = 10000;
+const DEFAULT_COUNT: usize = 100;
+// Entries read at a time when filtering by class or item
+const BATCH: usize = 500;
+
+#[derive(FromForm)]
+pub struct HistoryQuery {
+	pub class: Option<String>,
+	pub item: Option<String>,
+	// Unix seconds, both ends included
+	pub from: Option<u64>,
+	pub to: Option<u64>,
+	pub count: Option<usize>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+	// Stream entry id
+	pub id: String,
+	pub class: String,
+	pub item: String,
+	// Missing when the item wasn't in the inventory
+	pub old_donated: Option<bool>,
+	pub old_stock: Option<u32>,
+	pub donated: bool,
+	pub stock: u32,
+	pub timestamp: u64,
+	// Whoever made the change, the X-Client-Id header or the trade
+	pub client: String,
+}
+
+impl HistoryQuery {
+
+	/*
+	 * The stream ids to read between, they start with the time in milliseconds
+	 */
+	pub fn range(&self) -> Result<(String, String), HistoryError> {
+		let millis = |seconds: u64, extra: u64| seconds.checked_mul(1000).and_then(|ms| ms.checked_add(extra));
+		let start = match self.from {
+			Some(from) => millis(from, 0).map(|ms| ms.to_string()),
+			None => Some("-".to_string())
+		};
+		let end = match self.to {
+			Some(to) => millis(to, 999).map(|ms| ms.to_string()),
+			None => Some("+".to_string())
+		};
+		match (start, end) {
+			(Some(start), Some(end)) => Ok((start, end)),
+			_ => Err(HistoryError::new("Invalid time range"))
+		}
+	}
+}
+
+pub struct History;
+
+#[derive(Debug)]
+pub struct HistoryError {
+	pub details: String
+}
+
+impl HistoryError {
+	fn new(msg: &str) -> HistoryError {
+		HistoryError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for HistoryError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for HistoryError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+fn parse_entry(value: &Value) -> Option<HistoryEntry> {
+	let parts: Vec<Value> = redis::from_redis_value(value).ok()?;
+	if parts.len() != 2 {
+		return None
+	}
+	let id: String = redis::from_redis_value(&parts[0]).ok()?;
+	let fields: Vec<(String, String)> = redis::from_redis_value(&parts[1]).ok()?;
+	let field = |name: &str| fields.iter()
+		.find(|(key, _)| key == name)
+		.map(|(_, value)| value.to_owned())
+		.unwrap_or_default();
+	Some(HistoryEntry {
+		id: id,
+		class: field("class"),
+		item: field("item"),
+		old_donated: field("old_donated").parse().ok(),
+		old_stock: field("old_stock").parse().ok(),
+		donated: field("donated").parse().unwrap_or(false),
+		stock: field("stock").parse().unwrap_or(0),
+		timestamp: field("timestamp").parse().unwrap_or(0),
+		client: field("client"),
+	})
+}
+
+/*
+ * The stream id right before this one, XREVRANGE includes both ends
+ * and older servers don't take exclusive ones
+ */
+fn before(id: &str) -> Option<String> {
+	let mut parts = id.splitn(2, '-');
+	let ms: u64 = parts.next()?.parse().ok()?;
+	let seq: u64 = parts.next()?.parse().ok()?;
+	match (ms, seq) {
+		(0, 0) => None,
+		(ms, 0) => Some([(ms - 1).to_string(), "-".to_string(), u64::max_value().to_string()].concat()),
+		(ms, seq) => Some([ms.to_string(), "-".to_string(), (seq - 1).to_string()].concat())
+	}
+}
+
+impl History {
+
+	fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":history".to_string()].concat()
+	}
+
+	/*
+	 * Queues an entry for an item that changed, so it's
+	 * written along with the change itself
+	 */
+	pub(crate) fn record(
+						query: &mut Pipeline,
+						subscriber_id: &String,
+						old: &Option<Item>,
+						new: &Item,
+						client: &String
+					) {
+		query.cmd("XADD")
+			.arg(History::key(subscriber_id))
+			.arg("MAXLEN").arg("~").arg(MAX_ENTRIES)
+			.arg("*")
+			.arg("class").arg(&new.class)
+			.arg("item").arg(&new.id)
+			.arg("old_donated").arg(old.as_ref().map_or(String::new(), |old| old.donated.to_string()))
+			.arg("old_stock").arg(old.as_ref().map_or(String::new(), |old| old.stock.to_string()))
+			.arg("donated").arg(new.donated.to_string())
+			.arg("stock").arg(new.stock.to_string())
+			.arg("timestamp").arg(now())
+			.arg("client").arg(client)
+			.ignore();
+	}
+
+	/*
+	 * Changes to the subscriber's inventory, newest first
+	 */
+	pub fn get(redis: &DbConn, subscriber_id: &String, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, HistoryError> {
+		let (start, mut end) = query.range()?;
+		let count = query.count.unwrap_or(DEFAULT_COUNT).min(MAX_ENTRIES);
+		let filtered = query.class.is_some() || query.item.is_some();
+		// Without filters every entry read is kept, so only those are read
+		let batch = if filtered { BATCH } else { count };
+		let mut entries: Vec<HistoryEntry> = Vec::new();
+		loop {
+			let values = match redis::cmd("XREVRANGE")
+					.arg(History::key(subscriber_id))
+					.arg(&end)
+					.arg(&start)
+					.arg("COUNT")
+					.arg(batch)
+					.query::<Vec<Value>>(&**redis) {
+				Ok(values) => values,
+				Err(_e) => return Err(HistoryError::new("History not found"))
+			};
+			let last = values.last().and_then(parse_entry).map(|entry| entry.id);
+			entries.extend(values.iter()
+				.filter_map(parse_entry)
+				.filter(|entry| query.class.as_ref().map_or(true, |class| &entry.class == class))
+				.filter(|entry| query.item.as_ref().map_or(true, |item| &entry.item == item)));
+			if entries.len() >= count || values.len() < batch {
+				break
+			}
+			end = match last.as_ref().and_then(|last| before(last)) {
+				Some(end) => end,
+				None => break
+			};
+		}
+		entries.truncate(count);
+		Ok(entries)
+	}
+}
@@ -0,0 +1,265 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, Pipeline, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+
+use crate::lib::class::{Class, Item};
+use crate::lib::subscriber::Subscriber;
+
+const PAGE_SIZE: usize = 20;
+// How many ids are read at a time while skipping the opted out ones
+const CHUNK: isize = 100;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LeaderboardEntry {
+	pub rank: usize,
+	pub id: String,
+	pub name: String,
+	pub donated: u32,
+}
+
+pub struct Leaderboard;
+
+#[derive(Debug)]
+pub struct LeaderboardError {
+	pub details: String
+}
+
+impl LeaderboardError {
+	fn new(msg: &str) -> LeaderboardError {
+		LeaderboardError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for LeaderboardError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for LeaderboardError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+impl Leaderboard {
+
+	/*
+	 * The overall board is the subscribers set itself,
+	 * its score is the number of donated items
+	 */
+	pub(crate) fn key(class: &Option<String>) -> String {
+		match class {
+			Some(class) => ["leaderboard:".to_string(), class.to_owned()].concat(),
+			None => "subscribers".to_string()
+		}
+	}
+
+	pub(crate) fn optout_key() -> &'static str {
+		"leaderboard:optout"
+	}
+
+	pub(crate) fn counted_key() -> &'static str {
+		"leaderboard:counted"
+	}
+
+	/*
+	 * Queues the change of donated items of a class
+	 */
+	pub(crate) fn record(query: &mut Pipeline, subscriber_id: &String, class: &String, delta: i64) {
+		if delta == 0 {
+			return
+		}
+		query.zincr(Leaderboard::key(&None), subscriber_id, delta)
+			.ignore()
+			.zincr(Leaderboard::key(&Some(class.to_owned())), subscriber_id, delta)
+			.ignore();
+	}
+
+	/*
+	 * Counts the donated items of every class from scratch
+	 */
+	pub fn rebuild(redis: &DbConn, subscriber_id: &String) -> Result<(), LeaderboardError> {
+		let classes = match Class::list_classes(redis, subscriber_id) {
+			Ok(classes) => classes,
+			Err(e) => return Err(LeaderboardError::new(&e.details))
+		};
+		let mut query = pipe();
+		for class in &classes {
+			query.hgetall([subscriber_id.to_owned(), ":".to_string(), class.to_owned()].concat());
+		}
+		let values = if classes.is_empty() {
+			Vec::new()
+		} else {
+			match query.query::<Vec<Vec<(String, String)>>>(&**redis) {
+				Ok(values) => values,
+				Err(_e) => return Err(LeaderboardError::new("unknown error"))
+			}
+		};
+		let mut query = pipe();
+		query.atomic();
+		let mut total = 0;
+		for (class, items) in classes.iter().zip(values) {
+			let donated = items.iter()
+				.filter_map(|(id, meta)| Item::from_meta(class, id, meta))
+				.filter(|item| item.donated)
+				.count();
+			total += donated;
+			query.zadd(Leaderboard::key(&Some(class.to_owned())), subscriber_id, donated).ignore();
+		}
+		match query
+				.zadd(Leaderboard::key(&None), subscriber_id, total)
+				.ignore()
+				.sadd(Leaderboard::counted_key(), subscriber_id)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(LeaderboardError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Subscribers from before the boards were kept get counted once
+	 */
+	pub fn ensure_counted(redis: &DbConn, subscriber_id: &String) -> Result<(), LeaderboardError> {
+		match redis.sismember::<_,_,bool>(Leaderboard::counted_key(), subscriber_id) {
+			Ok(true) => Ok(()),
+			Ok(false) => Leaderboard::rebuild(redis, subscriber_id),
+			Err(_e) => Err(LeaderboardError::new("unknown error"))
+		}
+	}
+
+	fn validate(class: &Option<String>) -> Result<(), LeaderboardError> {
+		match class {
+			Some(class) if !Class(class.to_owned()).is_valid() => Err(LeaderboardError::new("Invalid class")),
+			_ => Ok(())
+		}
+	}
+
+	fn names(redis: &DbConn, ids: &Vec<String>) -> Result<Vec<String>, LeaderboardError> {
+		if ids.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for id in ids {
+			query.hget([id.to_owned(), ":profile".to_string()].concat(), "name");
+		}
+		match query.query::<Vec<Option<String>>>(&**redis) {
+			Ok(names) => Ok(names.into_iter().map(|name| name.unwrap_or_default()).collect()),
+			Err(_e) => Err(LeaderboardError::new("unknown error"))
+		}
+	}
+
+	fn entries(redis: &DbConn, ranked: Vec<(usize, String, u32)>) -> Result<Vec<LeaderboardEntry>, LeaderboardError> {
+		let ids: Vec<String> = ranked.iter().map(|(_, id, _)| id.to_owned()).collect();
+		let names = Leaderboard::names(redis, &ids)?;
+		Ok(ranked.into_iter()
+			.zip(names)
+			.map(|((rank, id, donated), name)| LeaderboardEntry {
+												rank: rank,
+												id: id,
+												name: name,
+												donated: donated
+											})
+			.collect())
+	}
+
+	/*
+	 * A page of the global board, overall or for a class. Subscribers who
+	 * opted out or haven't donated anything aren't ranked
+	 */
+	pub fn global(
+					redis: &DbConn,
+					subscriber_id: &String,
+					class: &Option<String>,
+					page: usize
+				) -> Result<Vec<LeaderboardEntry>, LeaderboardError> {
+		Leaderboard::validate(class)?;
+		Leaderboard::ensure_counted(redis, subscriber_id)?;
+		let key = Leaderboard::key(class);
+		let wanted = (page + 1) * PAGE_SIZE;
+		let mut ranked: Vec<(usize, String, u32)> = Vec::new();
+		let mut start: isize = 0;
+		while ranked.len() < wanted {
+			let chunk = match redis.zrevrange_withscores::<_, Vec<(String, i64)>>(&key, start, start + CHUNK - 1) {
+				Ok(chunk) => chunk,
+				Err(_e) => return Err(LeaderboardError::new("Leaderboard not found"))
+			};
+			let chunk: Vec<(String, u32)> = chunk.into_iter()
+				.filter(|(_, donated)| *donated > 0)
+				.map(|(id, donated)| (id, donated as u32))
+				.collect();
+			if chunk.is_empty() {
+				break;
+			}
+			let mut query = pipe();
+			for (id, _) in &chunk {
+				query.sismember(Leaderboard::optout_key(), id);
+			}
+			let hidden = match query.query::<Vec<bool>>(&**redis) {
+				Ok(hidden) => hidden,
+				Err(_e) => return Err(LeaderboardError::new("unknown error"))
+			};
+			let full = chunk.len() as isize == CHUNK;
+			for ((id, donated), hidden) in chunk.into_iter().zip(hidden) {
+				if !hidden && ranked.len() < wanted {
+					ranked.push((ranked.len() + 1, id, donated));
+				}
+			}
+			if !full {
+				break;
+			}
+			start += CHUNK;
+		}
+		Leaderboard::entries(redis, ranked.into_iter().skip(page * PAGE_SIZE).collect())
+	}
+
+	/*
+	 * A page of the board among the subscriber and their friends,
+	 * the subscriber is always on it
+	 */
+	pub fn friends(
+					redis: &DbConn,
+					subscriber: &mut Subscriber,
+					class: &Option<String>,
+					page: usize
+				) -> Result<Vec<LeaderboardEntry>, LeaderboardError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(LeaderboardError::new("No Id"))
+		};
+		Leaderboard::validate(class)?;
+		Leaderboard::ensure_counted(redis, &subscriber_id)?;
+		let friends = match subscriber.get_friends(redis) {
+			Ok(friends) => friends,
+			Err(e) => return Err(LeaderboardError::new(&e.details))
+		};
+		let mut ids: Vec<String> = friends.into_iter().map(|friend| friend.id).collect();
+		ids.push(subscriber_id.to_owned());
+		let key = Leaderboard::key(class);
+		let mut query = pipe();
+		for id in &ids {
+			query.zscore(&key, id)
+				.sismember(Leaderboard::optout_key(), id);
+		}
+		let values = match query.query::<Vec<(Option<i64>, bool)>>(&**redis) {
+			Ok(values) => values,
+			Err(_e) => return Err(LeaderboardError::new("unknown error"))
+		};
+		let mut scores: Vec<(String, u32)> = ids.into_iter()
+			.zip(values)
+			.filter(|(id, (_, hidden))| id == &subscriber_id || !hidden)
+			.map(|(id, (donated, _))| (id, donated.unwrap_or(0).max(0) as u32))
+			.collect();
+		scores.sort_by(|a, b| b.1.cmp(&a.1));
+		let ranked: Vec<(usize, String, u32)> = scores.into_iter()
+			.enumerate()
+			.map(|(index, (id, donated))| (index + 1, id, donated))
+			.skip(page * PAGE_SIZE)
+			.take(PAGE_SIZE)
+			.collect();
+		Leaderboard::entries(redis, ranked)
+	}
+}
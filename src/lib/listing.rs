@@ -0,0 +1,403 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use nanoid::nanoid;
+
+use crate::lib::class::Class;
+use crate::lib::trade::now;
+
+pub const KINDS: &[&str] = &["offering", "seeking"];
+pub const CURRENCIES: &[&str] = &["bells", "nmt"];
+
+// Listings live three days unless told otherwise and two weeks at most
+const DEFAULT_TTL: u64 = 3 * 24 * 60 * 60;
+const MAX_TTL: u64 = 14 * 24 * 60 * 60;
+const PAGE_SIZE: usize = 20;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Listing {
+	pub id: Option<String>,
+	pub owner: Option<String>,
+	pub kind: String,
+	pub class: String,
+	pub item: String,
+	pub quantity: u32,
+	pub price: u32,
+	pub currency: String,
+	pub expires: Option<u64>,
+	pub created: Option<u64>
+}
+
+#[derive(FromForm)]
+pub struct ListingQuery {
+	pub kind: Option<String>,
+	pub class: Option<String>,
+	pub item: Option<String>,
+	pub currency: Option<String>,
+	pub min_price: Option<u32>,
+	pub max_price: Option<u32>,
+	// "recent" (default) or "price"
+	pub sort: Option<String>,
+	pub page: Option<usize>
+}
+
+#[derive(Debug)]
+pub struct ListingError {
+	pub details: String
+}
+
+impl ListingError {
+	fn new(msg: &str) -> ListingError {
+		ListingError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for ListingError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for ListingError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+impl ListingQuery {
+	/*
+	 * Prices only compare within a currency
+	 */
+	pub fn validate(&self) -> Result<(), ListingError> {
+		if self.currency.is_none() && (self.min_price.is_some() || self.max_price.is_some()) {
+			return Err(ListingError::new("Price filters need a currency"))
+		}
+		if self.currency.is_none() && self.sort.as_ref().map(|sort| sort.as_str()) == Some("price") {
+			return Err(ListingError::new("Sorting by price needs a currency"))
+		}
+		if let Some(ref currency) = self.currency {
+			if !CURRENCIES.contains(&currency.as_str()) {
+				return Err(ListingError::new("Invalid currency"))
+			}
+		}
+		if let Some(ref kind) = self.kind {
+			if !KINDS.contains(&kind.as_str()) {
+				return Err(ListingError::new("Invalid kind"))
+			}
+		}
+		Ok(())
+	}
+}
+
+impl Listing {
+
+	fn key(id: &String) -> String {
+		["listing:".to_string(), id.to_owned()].concat()
+	}
+
+	fn owner_key(owner: &String) -> String {
+		[owner.to_owned(), ":listings".to_string()].concat()
+	}
+
+	fn class_key(class: &String) -> String {
+		["listings:class:".to_string(), class.to_owned()].concat()
+	}
+
+	fn price_key(currency: &String) -> String {
+		["listings:price:".to_string(), currency.to_owned()].concat()
+	}
+
+	fn kind_key(kind: &String) -> String {
+		["listings:kind:".to_string(), kind.to_owned()].concat()
+	}
+
+	fn item_key(item: &String) -> String {
+		["listings:item:".to_string(), item.to_owned()].concat()
+	}
+
+	fn from_hash(id: &String, value: HashMap<String, String>) -> Option<Listing> {
+		if value.is_empty() {
+			return None
+		}
+		let field = |name: &str| value.get(name).cloned().unwrap_or_default();
+		Some(Listing {
+			id: Some(id.to_owned()),
+			owner: Some(field("owner")),
+			kind: field("kind"),
+			class: field("class"),
+			item: field("item"),
+			quantity: field("quantity").parse().unwrap_or(0),
+			price: field("price").parse().unwrap_or(0),
+			currency: field("currency"),
+			expires: field("expires").parse().ok(),
+			created: field("created").parse().ok()
+		})
+	}
+
+	fn validate(&mut self) -> Result<(), ListingError> {
+		if !KINDS.contains(&self.kind.as_str()) {
+			return Err(ListingError::new("Invalid kind"))
+		}
+		if !CURRENCIES.contains(&self.currency.as_str()) {
+			return Err(ListingError::new("Invalid currency"))
+		}
+		if !Class(self.class.to_owned()).is_valid() || self.item.is_empty() {
+			return Err(ListingError::new("Invalid item"))
+		}
+		if self.quantity == 0 {
+			return Err(ListingError::new("Invalid quantity"))
+		}
+		let timestamp = now();
+		let expires = self.expires.unwrap_or(timestamp + DEFAULT_TTL);
+		if expires <= timestamp || expires > timestamp + MAX_TTL {
+			return Err(ListingError::new("Invalid expiry"))
+		}
+		self.expires = Some(expires);
+		Ok(())
+	}
+
+	/*
+	 * Removes a listing from every index
+	 */
+	fn remove(redis: &DbConn, listing: &Listing) -> Result<(), ListingError> {
+		let id = listing.id.clone().unwrap();
+		match pipe()
+				.atomic()
+				.del(Listing::key(&id))
+				.ignore()
+				.zrem("listings", &id)
+				.ignore()
+				.zrem("listings:expiry", &id)
+				.ignore()
+				.zrem(Listing::class_key(&listing.class), &id)
+				.ignore()
+				.zrem(Listing::price_key(&listing.currency), &id)
+				.ignore()
+				.zrem(Listing::kind_key(&listing.kind), &id)
+				.ignore()
+				.zrem(Listing::item_key(&listing.item), &id)
+				.ignore()
+				.srem(Listing::owner_key(&listing.owner.clone().unwrap_or_default()), &id)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(ListingError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Drops the listings past their expiry, it runs on every write
+	 * so the indexes don't grow, searches skip them on their own
+	 */
+	pub fn purge_expired(redis: &DbConn) -> Result<(), ListingError> {
+		let expired = match redis.zrangebyscore::<_,_,_,Vec<String>>("listings:expiry", "-inf", now()) {
+			Ok(ids) => ids,
+			Err(_e) => return Err(ListingError::new("unknown error"))
+		};
+		for id in expired {
+			let value = match redis.hgetall::<_, HashMap<String, String>>(Listing::key(&id)) {
+				Ok(value) => value,
+				Err(_e) => return Err(ListingError::new("unknown error"))
+			};
+			match Listing::from_hash(&id, value) {
+				Some(listing) => Listing::remove(redis, &listing)?,
+				None => {
+					let _ = redis.zrem::<_,_,u32>("listings", &id);
+					let _ = redis.zrem::<_,_,u32>("listings:expiry", &id);
+				}
+			}
+		}
+		Ok(())
+	}
+
+	/*
+	 * Gets a single listing
+	 */
+	pub fn get(redis: &DbConn, id: &String) -> Result<Listing, ListingError> {
+		match redis.hgetall::<_, HashMap<String, String>>(Listing::key(id)) {
+			Ok(value) => {
+				match Listing::from_hash(id, value) {
+					Some(ref listing) if listing.expires.unwrap_or(0) > now() => Ok(listing.clone()),
+					_ => Err(ListingError::new("Listing not found"))
+				}
+			},
+			Err(_e) => Err(ListingError::new("Listing not found"))
+		}
+	}
+
+	/*
+	 * Publishes a listing for the subscriber
+	 */
+	pub fn create(&mut self, redis: &DbConn, owner: &String) -> Result<(), ListingError> {
+		self.validate()?;
+		Listing::purge_expired(redis)?;
+		let id = nanoid!(12);
+		let timestamp = now();
+		self.id = Some(id);
+		self.owner = Some(owner.to_owned());
+		self.created = Some(timestamp);
+		self.save(redis)
+	}
+
+	/*
+	 * Changes a listing, only its owner can do it
+	 */
+	pub fn update(&mut self, redis: &DbConn, owner: &String, id: &String) -> Result<(), ListingError> {
+		let current = Listing::get(redis, id)?;
+		if current.owner.as_ref() != Some(owner) {
+			return Err(ListingError::new("Not the owner"))
+		}
+		self.validate()?;
+		Listing::purge_expired(redis)?;
+		Listing::remove(redis, &current)?;
+		self.id = current.id;
+		self.owner = current.owner;
+		self.created = current.created;
+		self.save(redis)
+	}
+
+	/*
+	 * Deletes a listing, only its owner can do it
+	 */
+	pub fn delete(redis: &DbConn, owner: &String, id: &String) -> Result<(), ListingError> {
+		let current = Listing::get(redis, id)?;
+		if current.owner.as_ref() != Some(owner) {
+			return Err(ListingError::new("Not the owner"))
+		}
+		Listing::remove(redis, &current)
+	}
+
+	fn save(&self, redis: &DbConn) -> Result<(), ListingError> {
+		let id = self.id.clone().unwrap();
+		let owner = self.owner.clone().unwrap();
+		let created = self.created.unwrap();
+		let expires = self.expires.unwrap();
+		match pipe()
+				.atomic()
+				.hset_multiple(Listing::key(&id), &[
+									("owner", owner.to_owned()),
+									("kind", self.kind.to_owned()),
+									("class", self.class.to_owned()),
+									("item", self.item.to_owned()),
+									("quantity", self.quantity.to_string()),
+									("price", self.price.to_string()),
+									("currency", self.currency.to_owned()),
+									("expires", expires.to_string()),
+									("created", created.to_string()),
+								])
+				.ignore()
+				// The hash outlives the expiry a little so purge can still clean the indexes
+				.expire_at(Listing::key(&id), (expires + DEFAULT_TTL) as usize)
+				.ignore()
+				.zadd("listings", &id, created)
+				.ignore()
+				.zadd("listings:expiry", &id, expires)
+				.ignore()
+				.zadd(Listing::class_key(&self.class), &id, created)
+				.ignore()
+				.zadd(Listing::price_key(&self.currency), &id, self.price)
+				.ignore()
+				.zadd(Listing::kind_key(&self.kind), &id, created)
+				.ignore()
+				.zadd(Listing::item_key(&self.item), &id, created)
+				.ignore()
+				.sadd(Listing::owner_key(&owner), &id)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(ListingError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Lists the subscriber's own listings
+	 */
+	pub fn list_owned(redis: &DbConn, owner: &String) -> Result<Vec<Listing>, ListingError> {
+		let timestamp = now();
+		match redis.smembers::<_, Vec<String>>(Listing::owner_key(owner)) {
+			Ok(ids) => Ok(Listing::load(redis, ids)?
+							.into_iter()
+							.filter(|listing| listing.expires.unwrap_or(0) > timestamp)
+							.collect()),
+			Err(_e) => Err(ListingError::new("Listings not found"))
+		}
+	}
+
+	fn load(redis: &DbConn, ids: Vec<String>) -> Result<Vec<Listing>, ListingError> {
+		if ids.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for id in &ids {
+			query.hgetall(Listing::key(id));
+		}
+		match query.query::<Vec<HashMap<String, String>>>(&**redis) {
+			Ok(value) => {
+				Ok(ids.iter()
+					.zip(value)
+					.filter_map(|(id, hash)| Listing::from_hash(id, hash))
+					.collect())
+			},
+			Err(_e) => Err(ListingError::new("Listings not found"))
+		}
+	}
+
+	/*
+	 * Searches the global index, price bounds need a currency.
+	 * The indexes of every filter are intersected into a scratch
+	 * key scored by what it's sorted on and only the page is read
+	 */
+	pub fn search(redis: &DbConn, query: &ListingQuery) -> Result<Vec<Listing>, ListingError> {
+		query.validate()?;
+		let scratch = ["listings:search:".to_string(), nanoid!(12)].concat();
+		// Scored by expiry first so the expired ones can be dropped
+		let mut filters = vec!["listings:expiry".to_string()];
+		if let Some(ref kind) = query.kind {
+			filters.push(Listing::kind_key(kind));
+		}
+		if let Some(ref class) = query.class {
+			filters.push(Listing::class_key(class));
+		}
+		if let Some(ref item) = query.item {
+			filters.push(Listing::item_key(item));
+		}
+		let mut weights = vec![1];
+		weights.resize(filters.len(), 0);
+		let by_price = query.sort.as_ref().map(|sort| sort.as_str()) == Some("price");
+		let start = (query.page.unwrap_or(0) * PAGE_SIZE) as isize;
+		let stop = start + PAGE_SIZE as isize - 1;
+
+		let mut search = pipe();
+		search.atomic()
+			.cmd("ZINTERSTORE").arg(&scratch).arg(filters.len()).arg(&filters).arg("WEIGHTS").arg(&weights)
+			.ignore()
+			.zrembyscore(&scratch, "-inf", now())
+			.ignore();
+		if let Some(ref currency) = query.currency {
+			search.cmd("ZINTERSTORE").arg(&scratch).arg(2).arg(&scratch).arg(Listing::price_key(currency)).arg("WEIGHTS").arg(0).arg(1)
+				.ignore();
+			if let Some(min) = query.min_price {
+				search.zrembyscore(&scratch, "-inf", ["(".to_string(), min.to_string()].concat())
+					.ignore();
+			}
+			if let Some(max) = query.max_price {
+				search.zrembyscore(&scratch, ["(".to_string(), max.to_string()].concat(), "+inf")
+					.ignore();
+			}
+		}
+		if by_price {
+			search.zrange(&scratch, start, stop);
+		} else {
+			search.cmd("ZINTERSTORE").arg(&scratch).arg(2).arg(&scratch).arg("listings").arg("WEIGHTS").arg(0).arg(1)
+				.ignore()
+				.zrevrange(&scratch, start, stop);
+		}
+		match search.del(&scratch).ignore().query::<Vec<Vec<String>>>(&**redis) {
+			Ok(mut pages) => Listing::load(redis, pages.pop().unwrap_or_default()),
+			Err(_e) => Err(ListingError::new("Listings not found"))
+		}
+	}
+}
@@ -1,8 +1,53 @@
 mod subscriber;
 mod class;
+mod trade;
+mod wishlist;
+mod listing;
+mod turnip;
+mod predictor;
+mod queue;
+mod catalog;
+mod villager;
+mod recipe;
+mod event;
+mod checklist;
+mod leaderboard;
+mod achievement;
+mod history;
+mod snapshot;
+mod sync;
+mod etag;
+mod transfer;
+mod account;
+mod archive;
+mod admin;
 pub mod api;
 
 pub use self::subscriber::Subscriber;
 pub use self::class::Class;
 pub use self::class::Item;
+pub use self::trade::TradeSuggestion;
+pub use self::trade::Trade;
+pub use self::wishlist::Wishlist;
+pub use self::listing::Listing;
+pub use self::turnip::TurnipWeek;
+pub use self::queue::Session;
+pub use self::catalog::{Catalog, LiveCatalog};
+pub use self::villager::{Resident, Dreamies};
+pub use self::recipe::RecipeCard;
+pub use self::event::EventDates;
+pub use self::checklist::Checklist;
+pub use self::leaderboard::Leaderboard;
+pub use self::achievement::Achievement;
+pub use self::history::History;
+pub use self::snapshot::Snapshot;
+pub use self::sync::Revision;
+pub use self::transfer::Export;
+#[cfg(test)] pub use self::transfer::{csv_field, csv_records, slug, class_name};
+pub use self::account::Account;
+pub use self::archive::DataExport;
+pub use self::admin::Admin;
+#[cfg(test)] pub use self::admin::repair_meta;
+#[cfg(test)] pub use self::history::HistoryQuery;
+pub use self::predictor::{predict, Pattern, PatternPrediction, PriceRange, TurnipPrediction};
 pub use self::api::*;
\ No newline at end of file
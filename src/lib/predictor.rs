@@ -0,0 +1,339 @@
+use crate::DbConn;
+use serde::{Deserialize, Serialize};
+use chrono::{Duration, NaiveDate};
+
+use crate::lib::turnip::{TurnipWeek, TurnipError, PERIODS, MIN_BUY, MAX_BUY};
+
+/*
+ * Turnip prices follow one of four patterns picked every Sunday,
+ * each pattern is a sequence of phases where the price is a rate
+ * of the buy price. This follows the model datamined from the game:
+ * https://gist.github.com/Treeki/85be14d297c80c8b3c0a76375743325b
+ */
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pattern {
+	Fluctuating,
+	LargeSpike,
+	Decreasing,
+	SmallSpike,
+}
+
+pub const PATTERNS: [Pattern; 4] = [
+	Pattern::Fluctuating,
+	Pattern::LargeSpike,
+	Pattern::Decreasing,
+	Pattern::SmallSpike,
+];
+
+// Chances of this week's pattern given last week's, in PATTERNS order
+const TRANSITIONS: [[f64; 4]; 4] = [
+	[0.20, 0.30, 0.15, 0.35],
+	[0.50, 0.05, 0.20, 0.25],
+	[0.25, 0.45, 0.05, 0.25],
+	[0.45, 0.25, 0.15, 0.15],
+];
+
+// Long run chances, used when last week's pattern is unknown
+const STEADY: [f64; 4] = [0.3463, 0.2474, 0.1476, 0.2587];
+
+const EPSILON: f64 = 0.000001;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PriceRange {
+	pub min: u32,
+	pub max: u32,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PatternPrediction {
+	pub pattern: Pattern,
+	pub probability: f64,
+	// One range per entry of PERIODS
+	pub ranges: Vec<PriceRange>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TurnipPrediction {
+	pub week: String,
+	pub previous: Option<Pattern>,
+	pub patterns: Vec<PatternPrediction>,
+	// Ranges over every possible pattern
+	pub ranges: Vec<PriceRange>,
+}
+
+/*
+ * A phase of the week
+ */
+#[derive(Clone, Copy)]
+enum Phase {
+	// Every half-day rolls its own rate
+	Random { len: usize, lo: f64, hi: f64 },
+	// The rate starts somewhere and drops a bit every half-day
+	Decreasing { len: usize, start: (f64, f64), step: (f64, f64) },
+	// Small spike peak, three half-days around a top rate of 1.4-2.0
+	SmallPeak,
+}
+
+impl Phase {
+	fn len(&self) -> usize {
+		match *self {
+			Phase::Random { len, .. } => len,
+			Phase::Decreasing { len, .. } => len,
+			Phase::SmallPeak => 3,
+		}
+	}
+}
+
+fn index(pattern: Pattern) -> usize {
+	PATTERNS.iter().position(|p| *p == pattern).unwrap()
+}
+
+fn price(rate: f64, base: f64) -> u32 {
+	let value = (rate * base - EPSILON).ceil();
+	if value < 0.0 { 0 } else { value as u32 }
+}
+
+/*
+ * Every way the game can lay out a pattern, with how likely it is
+ */
+fn variants(pattern: Pattern) -> Vec<(f64, Vec<Phase>)> {
+	let mut variants = Vec::new();
+	match pattern {
+		Pattern::Fluctuating => {
+			let high = |len| Phase::Random { len: len, lo: 0.9, hi: 1.4 };
+			let low = |len| Phase::Decreasing { len: len, start: (0.6, 0.8), step: (0.04, 0.1) };
+			for dec1 in 2..4 {
+				for hi1 in 0..7 {
+					let hi23 = 7 - hi1;
+					for hi3 in 0..hi23 {
+						let weight = 0.5 * (1.0 / 7.0) * (1.0 / hi23 as f64);
+						variants.push((weight, vec![
+							high(hi1), low(dec1), high(hi23 - hi3), low(5 - dec1), high(hi3),
+						]));
+					}
+				}
+			}
+		},
+		Pattern::LargeSpike => {
+			for start in 1..8 {
+				variants.push((1.0 / 7.0, vec![
+					Phase::Decreasing { len: start, start: (0.85, 0.9), step: (0.03, 0.05) },
+					Phase::Random { len: 1, lo: 0.9, hi: 1.4 },
+					Phase::Random { len: 1, lo: 1.4, hi: 2.0 },
+					Phase::Random { len: 1, lo: 2.0, hi: 6.0 },
+					Phase::Random { len: 1, lo: 1.4, hi: 2.0 },
+					Phase::Random { len: 1, lo: 0.9, hi: 1.4 },
+					Phase::Random { len: 7 - start, lo: 0.4, hi: 0.9 },
+				]));
+			}
+		},
+		Pattern::Decreasing => {
+			variants.push((1.0, vec![
+				Phase::Decreasing { len: 12, start: (0.85, 0.9), step: (0.03, 0.05) },
+			]));
+		},
+		Pattern::SmallSpike => {
+			for start in 0..8 {
+				variants.push((1.0 / 8.0, vec![
+					Phase::Decreasing { len: start, start: (0.4, 0.9), step: (0.03, 0.05) },
+					Phase::Random { len: 2, lo: 0.9, hi: 1.4 },
+					Phase::SmallPeak,
+					Phase::Decreasing { len: 7 - start, start: (0.4, 0.9), step: (0.03, 0.05) },
+				]));
+			}
+		},
+	}
+	variants
+}
+
+/*
+ * Price ranges of a phase for a buy price, None if the
+ * observed prices can't happen in it
+ */
+fn phase_ranges(phase: Phase, base: f64, observed: &[Option<u32>]) -> Option<Vec<PriceRange>> {
+	match phase {
+		Phase::Random { lo, hi, .. } => {
+			let (min, max) = (price(lo, base), price(hi, base));
+			let mut ranges = Vec::new();
+			for value in observed {
+				match *value {
+					Some(p) if p < min || p > max => return None,
+					Some(p) => ranges.push(PriceRange { min: p, max: p }),
+					None => ranges.push(PriceRange { min: min, max: max }),
+				}
+			}
+			Some(ranges)
+		},
+		Phase::Decreasing { start, step, .. } => {
+			// Narrow the possible rate of every half-day going forward...
+			let mut rates: Vec<(f64, f64)> = Vec::new();
+			let (mut lo, mut hi) = start;
+			for value in observed {
+				if let Some(p) = *value {
+					lo = lo.max((p as f64 - 1.0) / base);
+					hi = hi.min(p as f64 / base);
+					if lo > hi + EPSILON {
+						return None
+					}
+				}
+				rates.push((lo, hi));
+				lo -= step.1;
+				hi -= step.0;
+			}
+			// ...and back, later prices bound the earlier ones too
+			for i in (0..rates.len().saturating_sub(1)).rev() {
+				let next = rates[i + 1];
+				rates[i].0 = rates[i].0.max(next.0 + step.0);
+				rates[i].1 = rates[i].1.min(next.1 + step.1);
+			}
+			Some(rates.iter().zip(observed).map(|(rate, value)| match *value {
+				Some(p) => PriceRange { min: p, max: p },
+				None => PriceRange { min: price(rate.0, base), max: price(rate.1, base) },
+			}).collect())
+		},
+		Phase::SmallPeak => {
+			// The middle half-day sits at the top rate, its neighbours
+			// roll between 1.4 and the top rate and lose a bell
+			let (mut top_lo, mut top_hi): (f64, f64) = (1.4, 2.0);
+			if let Some(p) = observed[1] {
+				top_lo = top_lo.max((p as f64 - 1.0) / base);
+				top_hi = top_hi.min(p as f64 / base);
+			}
+			for value in &[observed[0], observed[2]] {
+				if let Some(p) = *value {
+					top_lo = top_lo.max(p as f64 / base);
+				}
+			}
+			if top_lo > top_hi + EPSILON {
+				return None
+			}
+			let side = PriceRange { min: price(1.4, base) - 1, max: price(top_hi, base) - 1 };
+			let top = PriceRange { min: price(top_lo, base), max: price(top_hi, base) };
+			let mut ranges = Vec::new();
+			for (value, range) in observed.iter().zip(&[side, top, side]) {
+				match *value {
+					Some(p) if p < range.min || p > range.max => return None,
+					Some(p) => ranges.push(PriceRange { min: p, max: p }),
+					None => ranges.push(*range),
+				}
+			}
+			Some(ranges)
+		},
+	}
+}
+
+fn variant_ranges(phases: &Vec<Phase>, base: f64, observed: &[Option<u32>]) -> Option<Vec<PriceRange>> {
+	let mut ranges = Vec::new();
+	let mut offset = 0;
+	for phase in phases {
+		let len = phase.len();
+		if len == 0 {
+			continue;
+		}
+		ranges.extend(phase_ranges(*phase, base, &observed[offset..offset + len])?);
+		offset += len;
+	}
+	Some(ranges)
+}
+
+fn merge(ranges: &mut Option<Vec<PriceRange>>, other: &Vec<PriceRange>) {
+	match ranges {
+		Some(ranges) => {
+			for (range, other) in ranges.iter_mut().zip(other) {
+				range.min = range.min.min(other.min);
+				range.max = range.max.max(other.max);
+			}
+		},
+		None => *ranges = Some(other.clone()),
+	}
+}
+
+/*
+ * Predicts the patterns that fit the week's prices. The chance of a pattern
+ * is its prior times the share of its layouts that fit the prices, which is
+ * close enough to the game's odds without weighing every possible rate.
+ * Returns None if the prices don't fit any pattern.
+ */
+pub fn predict(buy: Option<u32>, sell: &[Option<u32>], previous: Option<Pattern>) -> Option<Vec<PatternPrediction>> {
+	let observed: Vec<Option<u32>> = (0..PERIODS.len()).map(|i| sell.get(i).cloned().unwrap_or(None)).collect();
+	let bases: Vec<u32> = match buy {
+		Some(buy) => vec![buy],
+		None => (MIN_BUY..MAX_BUY + 1).collect(),
+	};
+	let priors = match previous {
+		Some(pattern) => TRANSITIONS[index(pattern)],
+		None => STEADY,
+	};
+
+	let mut predictions: Vec<PatternPrediction> = Vec::new();
+	let mut total = 0.0;
+	for pattern in PATTERNS.iter() {
+		let mut fit = 0.0;
+		let mut ranges: Option<Vec<PriceRange>> = None;
+		for (weight, phases) in variants(*pattern) {
+			for base in &bases {
+				if let Some(found) = variant_ranges(&phases, *base as f64, &observed) {
+					fit += weight / bases.len() as f64;
+					merge(&mut ranges, &found);
+				}
+			}
+		}
+		let probability = priors[index(*pattern)] * fit;
+		if let Some(ranges) = ranges {
+			if probability > 0.0 {
+				total += probability;
+				predictions.push(PatternPrediction {
+									pattern: *pattern,
+									probability: probability,
+									ranges: ranges,
+								});
+			}
+		}
+	}
+	if predictions.is_empty() {
+		return None
+	}
+	for prediction in predictions.iter_mut() {
+		prediction.probability /= total;
+	}
+	predictions.sort_by(|a, b| b.probability.partial_cmp(&a.probability).unwrap());
+	Some(predictions)
+}
+
+impl TurnipPrediction {
+
+	/*
+	 * Predicts a subscriber's week, last week's pattern is
+	 * used when its prices leave no doubt about it
+	 */
+	pub fn for_week(redis: &DbConn, subscriber_id: &String, week: &String) -> Result<TurnipPrediction, TurnipError> {
+		let turnips = TurnipWeek::get(redis, subscriber_id, week)?;
+		let mut previous: Option<Pattern> = None;
+		if let Ok(date) = NaiveDate::parse_from_str(week, "%Y-%m-%d") {
+			let last = (date - Duration::days(7)).format("%Y-%m-%d").to_string();
+			let last = TurnipWeek::get(redis, subscriber_id, &last)?;
+			if let Some(patterns) = predict(last.buy, &last.sell, None) {
+				if patterns.len() == 1 {
+					previous = Some(patterns[0].pattern);
+				}
+			}
+		}
+		match predict(turnips.buy, &turnips.sell, previous) {
+			Some(patterns) => {
+				let mut ranges: Option<Vec<PriceRange>> = None;
+				for pattern in &patterns {
+					merge(&mut ranges, &pattern.ranges);
+				}
+				Ok(TurnipPrediction {
+					week: week.to_owned(),
+					previous: previous,
+					patterns: patterns,
+					ranges: ranges.unwrap_or_default(),
+				})
+			},
+			None => Err(TurnipError::new("Prices don't match any pattern"))
+		}
+	}
+}
@@ -0,0 +1,355 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{self, Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use nanoid::nanoid;
+
+use crate::lib::subscriber::Subscriber;
+use crate::lib::trade::now;
+
+// Sessions are gone after six hours even if the host never closes them
+const SESSION_TTL: usize = 6 * 60 * 60;
+// Seconds an admitted visitor has to pick up the dodo code
+const DEFAULT_TIMEOUT: u64 = 180;
+const MAX_CAPACITY: u32 = 100;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Session {
+	pub id: Option<String>,
+	pub host: Option<String>,
+	pub description: String,
+	pub capacity: u32,
+	#[serde(default)]
+	pub friends_only: bool,
+	pub timeout: Option<u64>,
+	// Only the host and the admitted visitor get to see it
+	pub dodo: Option<String>,
+	pub admitted: Option<String>,
+	pub waiting: Option<u64>,
+	// Position of the caller in the queue, 1 is next
+	pub position: Option<u64>,
+	pub created: Option<u64>
+}
+
+#[derive(Debug)]
+pub struct QueueError {
+	pub details: String
+}
+
+impl QueueError {
+	fn new(msg: &str) -> QueueError {
+		QueueError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for QueueError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for QueueError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+impl Session {
+
+	fn key(id: &String) -> String {
+		["queue:".to_string(), id.to_owned()].concat()
+	}
+
+	fn visitors_key(id: &String) -> String {
+		["queue:".to_string(), id.to_owned(), ":visitors".to_string()].concat()
+	}
+
+	/*
+	 * Counts the joins, visitors are scored by it so
+	 * the ones joining in the same second keep their order
+	 */
+	fn tickets_key(id: &String) -> String {
+		["queue:".to_string(), id.to_owned(), ":tickets".to_string()].concat()
+	}
+
+	fn host_key(host: &String) -> String {
+		[host.to_owned(), ":queue".to_string()].concat()
+	}
+
+	fn load(redis: &DbConn, id: &String) -> Result<HashMap<String, String>, QueueError> {
+		match redis.hgetall::<_, HashMap<String, String>>(Session::key(id)) {
+			Ok(ref value) if !value.is_empty() => Ok(value.clone()),
+			_ => Err(QueueError::new("Queue not found"))
+		}
+	}
+
+	/*
+	 * Opens a queue, a host can only run one at a time
+	 */
+	pub fn open(&mut self, redis: &DbConn, host: &String) -> Result<(), QueueError> {
+		let dodo = match self.dodo {
+			Some(ref dodo) if dodo.len() == 5 && dodo.chars().all(|c| c.is_ascii_alphanumeric()) => dodo.to_uppercase(),
+			_ => return Err(QueueError::new("Invalid dodo code"))
+		};
+		if self.capacity == 0 || self.capacity > MAX_CAPACITY {
+			return Err(QueueError::new("Invalid capacity"))
+		}
+		if let Ok(Some(_)) = redis.get::<_, Option<String>>(Session::host_key(host)) {
+			return Err(QueueError::new("Queue already open"))
+		}
+		let id = nanoid!(12);
+		let timestamp = now();
+		let timeout = self.timeout.unwrap_or(DEFAULT_TIMEOUT);
+		match pipe()
+				.atomic()
+				.hset_multiple(Session::key(&id), &[
+									("host", host.to_owned()),
+									("description", self.description.to_owned()),
+									("capacity", self.capacity.to_string()),
+									("friends_only", self.friends_only.to_string()),
+									("timeout", timeout.to_string()),
+									("dodo", dodo.to_owned()),
+									("admitted", String::new()),
+									("admitted_at", "0".to_string()),
+									("claimed", "false".to_string()),
+									("created", timestamp.to_string()),
+								])
+				.ignore()
+				.expire(Session::key(&id), SESSION_TTL)
+				.ignore()
+				.set_ex(Session::host_key(host), &id, SESSION_TTL)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => {
+				self.id = Some(id);
+				self.host = Some(host.to_owned());
+				self.timeout = Some(timeout);
+				self.dodo = Some(dodo);
+				self.waiting = Some(0);
+				self.created = Some(timestamp);
+				Ok(())
+			},
+			Err(_e) => Err(QueueError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Gets the queue the subscriber is hosting
+	 */
+	pub fn hosted(redis: &DbConn, host: &String) -> Result<Session, QueueError> {
+		match redis.get::<_, Option<String>>(Session::host_key(host)) {
+			Ok(Some(id)) => Session::get(redis, host, &id),
+			_ => Err(QueueError::new("Queue not found"))
+		}
+	}
+
+	/*
+	 * The queues the subscriber is waiting in or was admitted to, by id
+	 */
+	pub(crate) fn joined(redis: &DbConn, subscriber_id: &String) -> Result<Vec<String>, QueueError> {
+		let ids: Vec<String> = match redis.scan_match::<_, String>("queue:*") {
+			Ok(keys) => keys.map(|key| key.trim_start_matches("queue:").to_string())
+							.filter(|id| !id.contains(':'))
+							.collect(),
+			Err(_e) => return Err(QueueError::new("Queues not found"))
+		};
+		let mut query = pipe();
+		for id in &ids {
+			query.hget(Session::key(id), "admitted")
+				.zscore(Session::visitors_key(id), subscriber_id);
+		}
+		match query.query::<Vec<(Option<String>, Option<f64>)>>(&**redis) {
+			Ok(values) => Ok(ids.into_iter().zip(values)
+								.filter(|(_, (admitted, score))| admitted.as_ref() == Some(subscriber_id) || score.is_some())
+								.map(|(id, _)| id)
+								.collect()),
+			Err(_e) => Err(QueueError::new("Queues not found"))
+		}
+	}
+
+	/*
+	 * Closes the queue, only the host can do it
+	 */
+	pub fn close(redis: &DbConn, host: &String, id: &String) -> Result<(), QueueError> {
+		let value = Session::load(redis, id)?;
+		if value.get("host") != Some(host) {
+			return Err(QueueError::new("Not the host"))
+		}
+		match pipe()
+				.atomic()
+				.del(Session::key(id))
+				.ignore()
+				.del(Session::visitors_key(id))
+				.ignore()
+				.del(Session::tickets_key(id))
+				.ignore()
+				.del(Session::host_key(host))
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(QueueError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Admits the next visitor in line, or nobody if the queue is empty.
+	 * With `replacing` it only happens if that visitor is still the
+	 * admitted one, so a no-show or a leave isn't skipped past twice
+	 */
+	fn admit_next(redis: &DbConn, id: &String, replacing: Option<&String>) -> Result<(), QueueError> {
+		let key = Session::key(id);
+		let visitors = Session::visitors_key(id);
+		let result = redis::transaction(&**redis, &[&key, &visitors], |pipe| {
+			if let Some(visitor) = replacing {
+				if redis.hget::<_, _, Option<String>>(&key, "admitted")?.as_ref() != Some(visitor) {
+					return Ok(Some(()))
+				}
+			}
+			let next = redis.zrange::<_, Vec<String>>(&visitors, 0, 0)?.into_iter().next().unwrap_or_default();
+			pipe.zrem(&visitors, &next)
+				.ignore()
+				.hset_multiple(&key, &[
+									("admitted", next.to_owned()),
+									("admitted_at", now().to_string()),
+									("claimed", "false".to_string()),
+								])
+				.ignore()
+				.query(&**redis)
+		});
+		match result {
+			Ok(()) => Ok(()),
+			Err(_e) => Err(QueueError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Skips the admitted visitor if they didn't pick up the code in time
+	 */
+	fn expire_no_show(redis: &DbConn, id: &String, value: &HashMap<String, String>) -> Result<bool, QueueError> {
+		let field = |name: &str| value.get(name).cloned().unwrap_or_default();
+		let admitted_at: u64 = field("admitted_at").parse().unwrap_or(0);
+		let timeout: u64 = field("timeout").parse().unwrap_or(DEFAULT_TIMEOUT);
+		if !field("admitted").is_empty() && field("claimed") != "true" && now() > admitted_at + timeout {
+			Session::admit_next(redis, id, Some(&field("admitted")))?;
+			return Ok(true)
+		}
+		Ok(false)
+	}
+
+	/*
+	 * Host moves the queue forward
+	 */
+	pub fn advance(redis: &DbConn, host: &String, id: &String) -> Result<Session, QueueError> {
+		let value = Session::load(redis, id)?;
+		if value.get("host") != Some(host) {
+			return Err(QueueError::new("Not the host"))
+		}
+		Session::admit_next(redis, id, None)?;
+		Session::get(redis, host, id)
+	}
+
+	/*
+	 * Gets the queue as the caller sees it, the admitted
+	 * visitor gets the dodo code and is no longer a no-show
+	 */
+	pub fn get(redis: &DbConn, subscriber_id: &String, id: &String) -> Result<Session, QueueError> {
+		let mut value = Session::load(redis, id)?;
+		if Session::expire_no_show(redis, id, &value)? {
+			value = Session::load(redis, id)?;
+		}
+		let field = |name: &str| value.get(name).cloned().unwrap_or_default();
+		let host = field("host");
+		let admitted = field("admitted");
+		let is_host = &host == subscriber_id;
+		let is_admitted = &admitted == subscriber_id;
+		if is_admitted && field("claimed") != "true" {
+			if redis.hset::<_,_,_,()>(Session::key(id), "claimed", "true").is_err() {
+				return Err(QueueError::new("unknown error"))
+			}
+		}
+		let (waiting, position) = match pipe()
+				.zcard(Session::visitors_key(id))
+				.zrank(Session::visitors_key(id), subscriber_id)
+				.query::<(u64, Option<u64>)>(&**redis) {
+			Ok(value) => value,
+			Err(_e) => return Err(QueueError::new("unknown error"))
+		};
+		Ok(Session {
+			id: Some(id.to_owned()),
+			host: Some(host.to_owned()),
+			description: field("description"),
+			capacity: field("capacity").parse().unwrap_or(0),
+			friends_only: field("friends_only") == "true",
+			timeout: field("timeout").parse().ok(),
+			dodo: if is_host || is_admitted { Some(field("dodo")) } else { None },
+			admitted: if admitted.is_empty() || !(is_host || is_admitted) { None } else { Some(admitted.to_owned()) },
+			waiting: Some(waiting),
+			position: position.map(|rank| rank + 1),
+			created: field("created").parse().ok()
+		})
+	}
+
+	/*
+	 * Joins the queue, friends only queues need the host to have
+	 * the visitor in their friends list
+	 */
+	pub fn join(redis: &DbConn, subscriber: &Subscriber, id: &String) -> Result<Session, QueueError> {
+		let visitor = match subscriber.id.clone() {
+			Some(visitor) => visitor,
+			None => return Err(QueueError::new("No Id"))
+		};
+		let value = Session::load(redis, id)?;
+		let field = |name: &str| value.get(name).cloned().unwrap_or_default();
+		let host = field("host");
+		if host == visitor {
+			return Err(QueueError::new("Host can't join their own queue"))
+		}
+		if field("friends_only") == "true" && !subscriber.is_friend_of(redis, &host) {
+			return Err(QueueError::new("Queue is for friends only"))
+		}
+		if field("admitted") != visitor {
+			let capacity: u64 = field("capacity").parse().unwrap_or(0);
+			let visitors = Session::visitors_key(id);
+			let tickets = Session::tickets_key(id);
+			// Under WATCH so joins at the same time can't overfill it
+			let mut full = false;
+			let result = redis::transaction(&**redis, &[&visitors], |pipe| {
+				full = false;
+				if redis.zscore::<_, _, Option<f64>>(&visitors, &visitor)?.is_some() {
+					return Ok(Some(()))
+				}
+				if redis.zcard::<_, u64>(&visitors)? >= capacity {
+					full = true;
+					return Ok(Some(()))
+				}
+				let ticket = redis.incr::<_, _, u64>(&tickets, 1)?;
+				pipe.zadd(&visitors, &visitor, ticket)
+					.ignore()
+					.expire(&visitors, SESSION_TTL)
+					.ignore()
+					.expire(&tickets, SESSION_TTL)
+					.ignore()
+					.query(&**redis)
+			});
+			match result {
+				Ok(()) if full => return Err(QueueError::new("Queue is full")),
+				Ok(()) => {},
+				Err(_e) => return Err(QueueError::new("unknown error"))
+			}
+		}
+		Session::get(redis, &visitor, id)
+	}
+
+	/*
+	 * Leaves the queue, an admitted visitor gives their turn to the next one
+	 */
+	pub fn leave(redis: &DbConn, subscriber_id: &String, id: &String) -> Result<(), QueueError> {
+		Session::load(redis, id)?;
+		if redis.zrem::<_,_,()>(Session::visitors_key(id), subscriber_id).is_err() {
+			return Err(QueueError::new("unknown error"))
+		}
+		Session::admit_next(redis, id, Some(subscriber_id))
+	}
+}
@@ -0,0 +1,294 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use crate::lib::catalog::Catalog;
+use crate::lib::subscriber::{Subscriber, Friend};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecipeCard {
+	pub id: String,
+	pub learned: bool,
+	// Cards of the recipe kept around to trade
+	#[serde(default)]
+	pub spare: u32,
+	// Where the subscriber got it, free text
+	#[serde(default)]
+	pub source: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CategoryStats {
+	pub category: String,
+	pub total: u32,
+	pub learned: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RecipeStats {
+	pub total: u32,
+	pub learned: u32,
+	pub percent: f64,
+	pub categories: Vec<CategoryStats>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SpareHolder {
+	pub friend: Friend,
+	pub recipes: Vec<RecipeCard>,
+}
+
+#[derive(Debug)]
+pub struct RecipeError {
+	pub details: String
+}
+
+impl RecipeError {
+	fn new(msg: &str) -> RecipeError {
+		RecipeError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for RecipeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for RecipeError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+impl RecipeCard {
+
+	fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":recipes".to_string()].concat()
+	}
+
+	/*
+	 * Cards are stored as "learned:spare:source"
+	 */
+	pub(crate) fn from_meta(id: &String, meta: &str) -> Option<RecipeCard> {
+		let mut parts = meta.splitn(3, ':');
+		let learned = parts.next()?.parse::<bool>().ok()?;
+		let spare = parts.next()?.parse::<u32>().ok()?;
+		Some(RecipeCard {
+			id: id.to_owned(),
+			learned: learned,
+			spare: spare,
+			source: parts.next().unwrap_or_default().to_string()
+		})
+	}
+
+	fn to_meta(&self) -> String {
+		[self.learned.to_string(), ":".to_string(), self.spare.to_string(), ":".to_string(), self.source.to_owned()].concat()
+	}
+
+	/*
+	 * Gets every card the subscriber has a record of
+	 */
+	pub fn get_all(redis: &DbConn, subscriber_id: &String) -> Result<Vec<RecipeCard>, RecipeError> {
+		match redis.hgetall::<
+								String,
+								Vec<(String, String)>
+							>(RecipeCard::key(subscriber_id)) {
+				Ok(value) => {
+					let mut cards: Vec<RecipeCard> = value.iter()
+						.filter_map(|(id, meta)| RecipeCard::from_meta(id, meta))
+						.collect();
+					cards.sort_by(|a, b| a.id.cmp(&b.id));
+					return Ok(cards)
+				},
+				Err(_e) => Err(RecipeError::new("Recipes not found"))
+		}
+	}
+
+	/*
+	 * Adds or updates cards, they must be in the bundled recipe list
+	 */
+	pub fn set(redis: &DbConn, subscriber_id: &String, cards: &Vec<RecipeCard>, catalog: &Catalog) -> Result<(), RecipeError> {
+		if cards.is_empty() {
+			return Ok(())
+		}
+		if let Some(card) = cards.iter().find(|card| catalog.recipe(&card.id).is_none()) {
+			return Err(RecipeError::new(&["Unknown recipe ".to_string(), card.id.to_owned()].concat()))
+		}
+		let values: Vec<(String, String)> = cards.iter()
+			.map(|card| (card.id.to_owned(), card.to_meta()))
+			.collect();
+		match redis.hset_multiple::<_,_,_,()>(RecipeCard::key(subscriber_id), &values) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(RecipeError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Adds one subscriber's cards to another's: learned if either learned it,
+	 * spare cards added up, the source they already had is kept
+	 */
+	pub(crate) fn merge(redis: &DbConn, from: &String, into: &String) -> Result<u32, RecipeError> {
+		let source = RecipeCard::get_all(redis, from)?;
+		if source.is_empty() {
+			return Ok(0)
+		}
+		let existing: HashMap<String, RecipeCard> = RecipeCard::get_all(redis, into)?
+			.into_iter()
+			.map(|card| (card.id.to_owned(), card))
+			.collect();
+		let values: Vec<(String, String)> = source.into_iter()
+			.map(|card| match existing.get(&card.id) {
+				Some(old) => RecipeCard {
+					learned: old.learned || card.learned,
+					spare: old.spare.saturating_add(card.spare),
+					source: if old.source.is_empty() { card.source.to_owned() } else { old.source.to_owned() },
+					..card
+				},
+				None => card
+			})
+			.map(|card| (card.id.to_owned(), card.to_meta()))
+			.collect();
+		match redis.hset_multiple::<_,_,_,()>(RecipeCard::key(into), &values) {
+			Ok(_) => Ok(values.len() as u32),
+			Err(_e) => Err(RecipeError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Marks recipes as learned or not, spare cards and sources are kept
+	 */
+	pub fn learn(
+				redis: &DbConn,
+				subscriber_id: &String,
+				ids: &Vec<String>,
+				learned: bool,
+				catalog: &Catalog
+			) -> Result<(), RecipeError> {
+		if ids.is_empty() {
+			return Ok(())
+		}
+		let mut query = pipe();
+		for id in ids {
+			query.hget(RecipeCard::key(subscriber_id), id);
+		}
+		let current = match query.query::<Vec<Option<String>>>(&**redis) {
+			Ok(value) => value,
+			Err(_e) => return Err(RecipeError::new("unknown error"))
+		};
+		let cards: Vec<RecipeCard> = ids.iter()
+			.zip(current)
+			.map(|(id, meta)| {
+				let mut card = meta.and_then(|meta| RecipeCard::from_meta(id, &meta))
+					.unwrap_or(RecipeCard {
+									id: id.to_owned(),
+									learned: false,
+									spare: 0,
+									source: String::new()
+								});
+				card.learned = learned;
+				card
+			})
+			.collect();
+		RecipeCard::set(redis, subscriber_id, &cards, catalog)
+	}
+
+	/*
+	 * Learned recipes against the bundled list, overall and per category
+	 */
+	pub fn stats(redis: &DbConn, subscriber_id: &String, catalog: &Catalog) -> Result<RecipeStats, RecipeError> {
+		let learned: Vec<String> = RecipeCard::get_all(redis, subscriber_id)?
+			.into_iter()
+			.filter(|card| card.learned)
+			.map(|card| card.id)
+			.collect();
+		let mut categories: Vec<CategoryStats> = Vec::new();
+		for recipe in &catalog.recipes {
+			let index = match categories.iter().position(|stats| stats.category == recipe.category) {
+				Some(index) => index,
+				None => {
+					categories.push(CategoryStats {
+										category: recipe.category.to_owned(),
+										total: 0,
+										learned: 0
+									});
+					categories.len() - 1
+				}
+			};
+			categories[index].total += 1;
+			if learned.contains(&recipe.id) {
+				categories[index].learned += 1;
+			}
+		}
+		let total = catalog.recipes.len() as u32;
+		let count: u32 = categories.iter().map(|stats| stats.learned).sum();
+		Ok(RecipeStats {
+			total: total,
+			learned: count,
+			percent: if total == 0 { 0.0 } else { count as f64 * 100.0 / total as f64 },
+			categories: categories
+		})
+	}
+
+	/*
+	 * Cards a subscriber can give away
+	 */
+	pub fn spares(redis: &DbConn, subscriber_id: &String) -> Result<Vec<RecipeCard>, RecipeError> {
+		Ok(RecipeCard::get_all(redis, subscriber_id)?
+			.into_iter()
+			.filter(|card| card.spare > 0)
+			.collect())
+	}
+
+	/*
+	 * Friends' spare cards of recipes the subscriber hasn't learned yet
+	 */
+	pub fn friend_spares(redis: &DbConn, subscriber: &mut Subscriber) -> Result<Vec<SpareHolder>, RecipeError> {
+		let subscriber_id = subscriber.id.clone().unwrap_or_default();
+		let friends = match subscriber.get_friends(redis) {
+			Ok(friends) => friends,
+			Err(e) => return Err(RecipeError::new(&e.details))
+		};
+		let friends = match subscriber.visible_friends(redis, friends) {
+			Ok(friends) => friends,
+			Err(e) => return Err(RecipeError::new(&e.details))
+		};
+		if friends.is_empty() {
+			return Ok(Vec::new())
+		}
+		let learned: Vec<String> = RecipeCard::get_all(redis, &subscriber_id)?
+			.into_iter()
+			.filter(|card| card.learned)
+			.map(|card| card.id)
+			.collect();
+		let mut query = pipe();
+		for friend in &friends {
+			query.hgetall(RecipeCard::key(&friend.id));
+		}
+		match query.query::<Vec<HashMap<String, String>>>(&**redis) {
+			Ok(value) => {
+				let mut holders: Vec<SpareHolder> = Vec::new();
+				for (friend, cards) in friends.into_iter().zip(value) {
+					let mut recipes: Vec<RecipeCard> = cards.iter()
+						.filter_map(|(id, meta)| RecipeCard::from_meta(id, meta))
+						.filter(|card| card.spare > 0 && !learned.contains(&card.id))
+						.collect();
+					if recipes.is_empty() {
+						continue;
+					}
+					recipes.sort_by(|a, b| a.id.cmp(&b.id));
+					holders.push(SpareHolder {
+									friend: friend,
+									recipes: recipes
+								});
+				}
+				holders.sort_by(|a, b| b.recipes.len().cmp(&a.recipes.len()));
+				return Ok(holders)
+			},
+			Err(_e) => Err(RecipeError::new("Recipes not found"))
+		}
+	}
+}
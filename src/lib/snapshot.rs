@@ -0,0 +1,400 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{self, Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use nanoid::nanoid;
+
+use crate::lib::class::{Class, Item};
+use crate::lib::history::History;
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::sync::Revision;
+use crate::lib::trade::now;
+
+// Retention, the oldest snapshots of each kind go first
+const MAX_MANUAL: isize = 10;
+const MAX_AUTO: isize = 5;
+// Writes this big get a snapshot first, unless there's a recent one
+pub const BULK_ITEMS: usize = 20;
+const AUTO_INTERVAL: u64 = 10 * 60;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+	pub id: Option<String>,
+	pub name: String,
+	// Taken by the server before a bulk write
+	#[serde(default)]
+	pub auto: bool,
+	pub items: Option<u32>,
+	pub created: Option<u64>
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SnapshotDiff {
+	pub class: String,
+	pub item: String,
+	// Missing on the side that doesn't have the item
+	pub snapshot: Option<Item>,
+	pub current: Option<Item>,
+}
+
+#[derive(Debug)]
+pub struct SnapshotError {
+	pub details: String
+}
+
+impl SnapshotError {
+	fn new(msg: &str) -> SnapshotError {
+		SnapshotError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for SnapshotError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for SnapshotError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+// class -> item -> "donated:stock"
+type Inventory = HashMap<String, HashMap<String, String>>;
+
+fn class_key(subscriber_id: &String, class: &String) -> String {
+	[subscriber_id.to_owned(), ":".to_string(), class.to_owned()].concat()
+}
+
+/*
+ * Every item that isn't the same on both sides
+ */
+fn diff(snapshot: &Inventory, current: &Inventory) -> Vec<SnapshotDiff> {
+	let mut classes: Vec<&String> = snapshot.keys().chain(current.keys()).collect();
+	classes.sort();
+	classes.dedup();
+	let empty: HashMap<String, String> = HashMap::new();
+	let mut changes: Vec<SnapshotDiff> = Vec::new();
+	for class in classes {
+		let old = snapshot.get(class).unwrap_or(&empty);
+		let new = current.get(class).unwrap_or(&empty);
+		let mut items: Vec<&String> = old.keys().chain(new.keys()).collect();
+		items.sort();
+		items.dedup();
+		for item in items {
+			let (old, new) = (old.get(item), new.get(item));
+			if old != new {
+				changes.push(SnapshotDiff {
+								class: class.to_owned(),
+								item: item.to_owned(),
+								snapshot: old.and_then(|meta| Item::from_meta(class, item, meta)),
+								current: new.and_then(|meta| Item::from_meta(class, item, meta)),
+							});
+			}
+		}
+	}
+	changes
+}
+
+impl Snapshot {
+
+	fn key(subscriber_id: &String, id: &String) -> String {
+		[subscriber_id.to_owned(), ":snapshot:".to_string(), id.to_owned()].concat()
+	}
+
+	fn info_key(subscriber_id: &String, id: &String) -> String {
+		[subscriber_id.to_owned(), ":snapshot:".to_string(), id.to_owned(), ":info".to_string()].concat()
+	}
+
+	fn index_key(subscriber_id: &String, auto: bool) -> String {
+		let kind = if auto { ":snapshots:auto" } else { ":snapshots" };
+		[subscriber_id.to_owned(), kind.to_string()].concat()
+	}
+
+	/*
+	 * Reads every class hash of the subscriber
+	 */
+	fn inventory(redis: &DbConn, subscriber_id: &String) -> Result<Inventory, SnapshotError> {
+		let classes = match Class::list_classes(redis, subscriber_id) {
+			Ok(classes) => classes,
+			Err(e) => return Err(SnapshotError::new(&e.details))
+		};
+		if classes.is_empty() {
+			return Ok(HashMap::new())
+		}
+		let mut query = pipe();
+		for class in &classes {
+			query.hgetall(class_key(subscriber_id, class));
+		}
+		match query.query::<Vec<HashMap<String, String>>>(&**redis) {
+			Ok(values) => Ok(classes.into_iter().zip(values).collect()),
+			Err(_e) => Err(SnapshotError::new("Classes not found"))
+		}
+	}
+
+	/*
+	 * Reads a snapshot, its hash fields are "class:item"
+	 */
+	fn load(redis: &DbConn, subscriber_id: &String, id: &String) -> Result<Inventory, SnapshotError> {
+		match redis.exists::<_, bool>(Snapshot::info_key(subscriber_id, id)) {
+			Ok(true) => {},
+			_ => return Err(SnapshotError::new("Snapshot not found"))
+		}
+		match redis.hgetall::<_, Vec<(String, String)>>(Snapshot::key(subscriber_id, id)) {
+			Ok(values) => {
+				let mut inventory: Inventory = HashMap::new();
+				for (field, meta) in values {
+					let mut parts = field.splitn(2, ':');
+					if let (Some(class), Some(item)) = (parts.next(), parts.next()) {
+						inventory.entry(class.to_string()).or_insert(HashMap::new()).insert(item.to_string(), meta);
+					}
+				}
+				Ok(inventory)
+			},
+			Err(_e) => Err(SnapshotError::new("Snapshot not found"))
+		}
+	}
+
+	fn from_hash(id: &String, value: HashMap<String, String>) -> Option<Snapshot> {
+		if value.is_empty() {
+			return None
+		}
+		let field = |name: &str| value.get(name).cloned().unwrap_or_default();
+		Some(Snapshot {
+			id: Some(id.to_owned()),
+			name: field("name"),
+			auto: field("auto") == "true",
+			items: field("items").parse().ok(),
+			created: field("created").parse().ok()
+		})
+	}
+
+	/*
+	 * Copies every class of the subscriber into a new snapshot
+	 */
+	pub fn create(&mut self, redis: &DbConn, subscriber_id: &String) -> Result<(), SnapshotError> {
+		self.save(redis, subscriber_id)?;
+		Snapshot::prune(redis, subscriber_id, self.auto, None)
+	}
+
+	fn save(&mut self, redis: &DbConn, subscriber_id: &String) -> Result<(), SnapshotError> {
+		if self.name.is_empty() || self.name.len() > 64 {
+			return Err(SnapshotError::new("Invalid name"))
+		}
+		let inventory = Snapshot::inventory(redis, subscriber_id)?;
+		let id = nanoid!(12);
+		let timestamp = now();
+		let mut fields: Vec<(String, String)> = Vec::new();
+		for (class, items) in &inventory {
+			for (item, meta) in items {
+				fields.push(([class.to_owned(), ":".to_string(), item.to_owned()].concat(), meta.to_owned()));
+			}
+		}
+		let mut query = pipe();
+		query.atomic();
+		if !fields.is_empty() {
+			query.hset_multiple(Snapshot::key(subscriber_id, &id), &fields).ignore();
+		}
+		match query
+				.hset_multiple(Snapshot::info_key(subscriber_id, &id), &[
+									("name", self.name.to_owned()),
+									("auto", self.auto.to_string()),
+									("items", fields.len().to_string()),
+									("created", timestamp.to_string()),
+								])
+				.ignore()
+				.zadd(Snapshot::index_key(subscriber_id, self.auto), &id, timestamp)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => {},
+			Err(_e) => return Err(SnapshotError::new("unknown error"))
+		}
+		self.id = Some(id);
+		self.items = Some(fields.len() as u32);
+		self.created = Some(timestamp);
+		Ok(())
+	}
+
+	/*
+	 * Snapshot taken by the server before a bulk write,
+	 * skipped if there's one from a few minutes ago
+	 */
+	pub fn auto(redis: &DbConn, subscriber_id: &String, reason: &str) -> Result<(), SnapshotError> {
+		let last = match redis.zrevrange_withscores::<_, Vec<(String, u64)>>(Snapshot::index_key(subscriber_id, true), 0, 0) {
+			Ok(last) => last,
+			Err(_e) => return Err(SnapshotError::new("unknown error"))
+		};
+		if let Some((_, created)) = last.first() {
+			if now() < created + AUTO_INTERVAL {
+				return Ok(())
+			}
+		}
+		let mut snapshot = Snapshot {
+			id: None,
+			name: ["Before ".to_string(), reason.to_string()].concat(),
+			auto: true,
+			items: None,
+			created: None
+		};
+		snapshot.create(redis, subscriber_id)
+	}
+
+	/*
+	 * Drops the oldest snapshots past the retention cap,
+	 * `keep` stays and doesn't count towards it
+	 */
+	fn prune(redis: &DbConn, subscriber_id: &String, auto: bool, keep: Option<&String>) -> Result<(), SnapshotError> {
+		let max = if auto { MAX_AUTO } else { MAX_MANUAL };
+		let ids = match redis.zrevrange::<_, Vec<String>>(Snapshot::index_key(subscriber_id, auto), 0, -1) {
+			Ok(ids) => ids,
+			Err(_e) => return Err(SnapshotError::new("unknown error"))
+		};
+		for id in ids.iter().filter(|id| Some(*id) != keep).skip(max as usize) {
+			Snapshot::delete(redis, subscriber_id, id)?;
+		}
+		Ok(())
+	}
+
+	/*
+	 * Lists the snapshots, newest first
+	 */
+	pub fn list(redis: &DbConn, subscriber_id: &String) -> Result<Vec<Snapshot>, SnapshotError> {
+		let ids = match pipe()
+				.zrevrange(Snapshot::index_key(subscriber_id, false), 0, -1)
+				.zrevrange(Snapshot::index_key(subscriber_id, true), 0, -1)
+				.query::<(Vec<String>, Vec<String>)>(&**redis) {
+			Ok((manual, auto)) => manual.into_iter().chain(auto).collect::<Vec<String>>(),
+			Err(_e) => return Err(SnapshotError::new("Snapshots not found"))
+		};
+		if ids.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for id in &ids {
+			query.hgetall(Snapshot::info_key(subscriber_id, id));
+		}
+		match query.query::<Vec<HashMap<String, String>>>(&**redis) {
+			Ok(values) => {
+				let mut snapshots: Vec<Snapshot> = ids.iter()
+					.zip(values)
+					.filter_map(|(id, value)| Snapshot::from_hash(id, value))
+					.collect();
+				snapshots.sort_by(|a, b| b.created.cmp(&a.created));
+				Ok(snapshots)
+			},
+			Err(_e) => Err(SnapshotError::new("Snapshots not found"))
+		}
+	}
+
+	/*
+	 * What changed between a snapshot and the current inventory
+	 */
+	pub fn diff(redis: &DbConn, subscriber_id: &String, id: &String) -> Result<Vec<SnapshotDiff>, SnapshotError> {
+		let snapshot = Snapshot::load(redis, subscriber_id, id)?;
+		let current = Snapshot::inventory(redis, subscriber_id)?;
+		Ok(diff(&snapshot, &current))
+	}
+
+	/*
+	 * Puts every class back the way it was in the snapshot, classes
+	 * made after it are emptied. The current inventory is snapshotted
+	 * first so the restore can be undone.
+	 */
+	pub fn restore(redis: &DbConn, subscriber_id: &String, id: &String) -> Result<Vec<SnapshotDiff>, SnapshotError> {
+		let snapshot = Snapshot::load(redis, subscriber_id, id)?;
+		if let Err(e) = Leaderboard::ensure_counted(redis, subscriber_id) {
+			return Err(SnapshotError::new(&e.details))
+		}
+		// Kept with the automatic ones so it doesn't push out manual snapshots,
+		// the one being restored isn't pushed out either
+		let mut undo = Snapshot {
+			id: None,
+			name: "Before restoring a snapshot".to_string(),
+			auto: true,
+			items: None,
+			created: None
+		};
+		undo.save(redis, subscriber_id)?;
+		Snapshot::prune(redis, subscriber_id, true, Some(id))?;
+		let current = Snapshot::inventory(redis, subscriber_id)?;
+		let mut keys: Vec<String> = snapshot.keys().chain(current.keys())
+			.map(|class| class_key(subscriber_id, class))
+			.collect();
+		keys.sort();
+		keys.dedup();
+		keys.push(Revision::key(subscriber_id));
+		keys.push(Class::index_key(subscriber_id));
+		let client = ["snapshot:".to_string(), id.to_owned()].concat();
+		let mut changes: Vec<SnapshotDiff> = Vec::new();
+		let result = redis::transaction(&**redis, &keys, |pipe| {
+			// Read again under WATCH, a write could have slipped in
+			let current = match Snapshot::inventory(redis, subscriber_id) {
+				Ok(current) => current,
+				Err(_e) => return Err((redis::ErrorKind::TypeError, "Classes not found").into())
+			};
+			changes = diff(&snapshot, &current);
+			let mut deltas: HashMap<&String, i64> = HashMap::new();
+			let mut changed: HashMap<&String, Vec<&String>> = HashMap::new();
+			for change in &changes {
+				changed.entry(&change.class).or_insert(Vec::new()).push(&change.item);
+				let was = change.current.as_ref().map_or(false, |item| item.donated);
+				let will = change.snapshot.as_ref().map_or(false, |item| item.donated);
+				*deltas.entry(&change.class).or_insert(0) += will as i64 - was as i64;
+				let new = change.snapshot.clone().unwrap_or(Item {
+														class: change.class.to_owned(),
+														id: change.item.to_owned(),
+														donated: false,
+														stock: 0,
+													});
+				History::record(pipe, subscriber_id, &change.current, &new, &client);
+			}
+			for class in current.keys() {
+				pipe.del(class_key(subscriber_id, class))
+					.ignore()
+					.srem(Class::index_key(subscriber_id), class)
+					.ignore();
+			}
+			for (class, items) in &snapshot {
+				let items: Vec<(&String, &String)> = items.iter().collect();
+				if !items.is_empty() {
+					pipe.hset_multiple(class_key(subscriber_id, class), &items).ignore();
+					Class::index(pipe, subscriber_id, class);
+				}
+			}
+			for (class, delta) in deltas {
+				Leaderboard::record(pipe, subscriber_id, class, delta);
+			}
+			for (class, items) in changed {
+				Revision::record(redis, pipe, subscriber_id, class, &items)?;
+			}
+			pipe.query(&**redis)
+		});
+		match result {
+			Ok(()) => Ok(changes),
+			Err(_e) => Err(SnapshotError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Forgets a snapshot
+	 */
+	pub fn delete(redis: &DbConn, subscriber_id: &String, id: &String) -> Result<(), SnapshotError> {
+		match pipe()
+				.atomic()
+				.del(Snapshot::key(subscriber_id, id))
+				.ignore()
+				.del(Snapshot::info_key(subscriber_id, id))
+				.ignore()
+				.zrem(Snapshot::index_key(subscriber_id, false), id)
+				.ignore()
+				.zrem(Snapshot::index_key(subscriber_id, true), id)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(SnapshotError::new("unknown error"))
+		}
+	}
+}
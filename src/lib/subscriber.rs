@@ -1,9 +1,16 @@
 use crate::DbConn;  
-use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use rocket_contrib::databases::redis::{self, Commands, pipe, Pipeline, PipelineCommands};
 use serde::{Deserialize, Serialize};
 use std::error;
 use std::fmt;
 use nanoid::nanoid;
+use chrono_tz::Tz;
+
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::achievement::{Achievement, Badge};
+use crate::lib::account::Account;
+use crate::lib::admin::Admin;
+use crate::lib::etag;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Subscriber {
@@ -18,9 +25,152 @@ pub struct Friend {
 	pub name: String
 }
 
+/*
+ * Only the name is required when it's posted, the other fields
+ * keep their stored value when they're left out
+ */
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Profile {
-	pub name: String
+	pub name: String,
+	// Who can see the inventory: "public", "friends" or "private"
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub visibility: Option<String>,
+	// IANA timezone of the island, e.g. "America/Mexico_City"
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub timezone: Option<String>,
+	// "north" or "south", seasons are flipped between them
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub hemisphere: Option<String>,
+	// Shows up on the completion leaderboards
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub leaderboard: Option<bool>,
+	// Worked out by the server, never taken from the client
+	#[serde(default, skip_deserializing)]
+	pub achievements: Vec<Badge>
+}
+
+fn default_visibility() -> String {
+	"public".to_string()
+}
+
+fn default_timezone() -> String {
+	"UTC".to_string()
+}
+
+fn default_hemisphere() -> String {
+	"north".to_string()
+}
+
+fn default_leaderboard() -> bool {
+	true
+}
+
+pub const VISIBILITIES: &[&str] = &["public", "friends", "private"];
+pub const HEMISPHERES: &[&str] = &["north", "south"];
+// Suspended subscribers can sign in but get this instead of their id
+const SUSPENDED: &str = "Subscriber suspended";
+
+/*
+ * A missing visibility is public, "friends" needs the owner
+ * to have the viewer in their friends list
+ */
+fn visibility_allows(visibility: Option<String>, is_friend: bool) -> bool {
+	match visibility.as_ref().map(|v| v.as_str()) {
+		Some("private") => false,
+		Some("friends") => is_friend,
+		_ => true
+	}
+}
+
+/*
+ * Friends go by name, the hash order changes as it grows
+ * and the list's ETag shouldn't
+ */
+fn sort_friends(friends: &mut Vec<Friend>) {
+	friends.sort_by(|a, b| (&a.name, &a.id).cmp(&(&b.name, &b.id)));
+}
+
+fn friends_from_hash(value: Vec<(String, String)>) -> Vec<Friend> {
+	let mut friends: Vec<Friend> = value.into_iter()
+		.map(|(id, name)| Friend { id: id, name: name })
+		.collect();
+	sort_friends(&mut friends);
+	friends
+}
+
+impl Profile {
+	/*
+	 * Checks the fields that only take a few values
+	 */
+	pub fn validate(&self) -> Result<(), SubscriberError> {
+		if let Some(ref visibility) = self.visibility {
+			if !VISIBILITIES.contains(&visibility.as_str()) {
+				return Err(SubscriberError::new("Invalid visibility"))
+			}
+		}
+		if let Some(ref timezone) = self.timezone {
+			if timezone.parse::<Tz>().is_err() {
+				return Err(SubscriberError::new("Invalid timezone"))
+			}
+		}
+		if let Some(ref hemisphere) = self.hemisphere {
+			if !HEMISPHERES.contains(&hemisphere.as_str()) {
+				return Err(SubscriberError::new("Invalid hemisphere"))
+			}
+		}
+		Ok(())
+	}
+
+	/*
+	 * Builds the profile from the fields of the profile hash,
+	 * the ones that aren't there stay None
+	 */
+	pub(crate) fn from_hash(value: Vec<(String, String)>) -> Profile {
+		let mut profile = Profile {
+			name: String::new(),
+			visibility: None,
+			timezone: None,
+			hemisphere: None,
+			leaderboard: None,
+			achievements: Vec::new()
+		};
+		for (field, val) in value {
+			match field.as_str() {
+				"name" => profile.name = val,
+				"visibility" => profile.visibility = Some(val),
+				"timezone" => profile.timezone = Some(val),
+				"hemisphere" => profile.hemisphere = Some(val),
+				"leaderboard" => profile.leaderboard = Some(val != "false"),
+				_ => {}
+			}
+		}
+		profile
+	}
+
+	/*
+	 * Fills what was never set, so stored profiles always show every field
+	 */
+	fn with_defaults(self) -> Profile {
+		Profile {
+			visibility: Some(self.visibility.unwrap_or_else(default_visibility)),
+			timezone: Some(self.timezone.unwrap_or_else(default_timezone)),
+			hemisphere: Some(self.hemisphere.unwrap_or_else(default_hemisphere)),
+			leaderboard: Some(self.leaderboard.unwrap_or_else(default_leaderboard)),
+			..self
+		}
+	}
+
+	/*
+	 * Whether writing this profile would change the stored one,
+	 * the fields left out don't count
+	 */
+	pub(crate) fn changes(&self, old: &Profile) -> bool {
+		self.name != old.name
+			|| self.visibility.as_ref().map_or(false, |visibility| Some(visibility) != old.visibility.as_ref())
+			|| self.timezone.as_ref().map_or(false, |timezone| Some(timezone) != old.timezone.as_ref())
+			|| self.hemisphere.as_ref().map_or(false, |hemisphere| Some(hemisphere) != old.hemisphere.as_ref())
+			|| self.leaderboard.map_or(false, |leaderboard| Some(leaderboard) != old.leaderboard)
+	}
 }
 
 #[derive(Debug, Clone)]
@@ -47,6 +197,14 @@ impl SubscriberError {
     fn new(msg: &str) -> SubscriberError {
         SubscriberError{details: msg.to_string()}
     }
+
+    pub fn is_suspended(&self) -> bool {
+        self.details == SUSPENDED
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.details == etag::CHANGED
+    }
 }
 
 impl fmt::Display for SubscriberError {
@@ -104,6 +262,108 @@ impl Subscriber {
 		}
 	 }
 
+	/*
+	 * Checks if the subscriber is in the friends list of `owner_id`
+	 */
+	pub fn is_friend_of(&self, redis: &DbConn, owner_id: &String) -> bool {
+		if self.id.is_none() {
+			return false
+		}
+		let friends = &[owner_id.to_owned(), ":friends".to_string()].concat();
+		match redis.hexists::<_,_,bool>(friends, self.id.clone().unwrap()) {
+			Ok(value) => value,
+			Err(_e) => false
+		}
+	}
+
+	/*
+	 * Checks if the subscriber can see the inventory of `owner_id`
+	 */
+	pub fn can_view(&self, redis: &DbConn, owner_id: &String) -> bool {
+		if self.id.as_ref() == Some(owner_id) {
+			return true
+		}
+		let profile = &[owner_id.to_owned(), ":profile".to_string()].concat();
+		let friends = &[owner_id.to_owned(), ":friends".to_string()].concat();
+		let me = self.id.clone().unwrap_or_default();
+		match pipe()
+				.hget(profile, "visibility")
+				.hexists(friends, &me)
+				.query::<(Option<String>, bool)>(&**redis) {
+			Ok((visibility, is_friend)) => visibility_allows(visibility, is_friend),
+			Err(_e) => false
+		}
+	}
+
+	/*
+	 * Keeps the friends whose inventory the subscriber can see
+	 */
+	pub fn visible_friends(&self, redis: &DbConn, friends: Vec<Friend>) -> Result<Vec<Friend>, SubscriberError> {
+		if self.id.is_none() {
+			return Err(SubscriberError::new("No Id"))
+		}
+		if friends.is_empty() {
+			return Ok(friends)
+		}
+		let me = self.id.clone().unwrap();
+		let mut query = pipe();
+		for friend in &friends {
+			query.hget([friend.id.to_owned(), ":profile".to_string()].concat(), "visibility")
+				 .hexists([friend.id.to_owned(), ":friends".to_string()].concat(), &me);
+		}
+		match query.query::<Vec<(Option<String>, bool)>>(&**redis) {
+			Ok(value) => {
+				let mut visible: Vec<Friend> = Vec::new();
+				for (friend, (visibility, is_friend)) in friends.into_iter().zip(value) {
+					if visibility_allows(visibility, is_friend) {
+						visible.push(friend);
+					}
+				}
+				return Ok(visible)
+			},
+			Err(_e) => Err(SubscriberError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Gets the timezone of the subscriber's island, UTC if it was never set
+	 */
+	pub fn get_timezone(&self, redis: &DbConn) -> Tz {
+		if self.id.is_none() {
+			return Tz::UTC
+		}
+		let query = &[self.id.clone().unwrap(), ":profile".to_string()].concat();
+		match redis.hget::<_,_,Option<String>>(query, "timezone") {
+			Ok(Some(value)) => value.parse::<Tz>().unwrap_or(Tz::UTC),
+			_ => Tz::UTC
+		}
+	}
+
+	/*
+	 * Gets the hemisphere of the subscriber's island, north if it was never set
+	 */
+	pub fn get_hemisphere(&self, redis: &DbConn) -> String {
+		if self.id.is_none() {
+			return default_hemisphere()
+		}
+		let query = &[self.id.clone().unwrap(), ":profile".to_string()].concat();
+		match redis.hget::<_,_,Option<String>>(query, "hemisphere") {
+			Ok(Some(ref value)) if HEMISPHERES.contains(&value.as_str()) => value.to_owned(),
+			_ => default_hemisphere()
+		}
+	}
+
+	/*
+	 * Suspended subscribers are turned away wherever they sign in
+	 */
+	fn check_suspension(redis: &DbConn, id: &String) -> Result<(), SubscriberError> {
+		match Admin::suspension(redis, id) {
+			Ok(None) => Ok(()),
+			Ok(Some(_reason)) => Err(SubscriberError::new(SUSPENDED)),
+			Err(_e) => Err(SubscriberError::new("unknown error"))
+		}
+	}
+
 	/*
 	 * Gets the subscriber id
 	 */
@@ -128,6 +388,7 @@ impl Subscriber {
 			return Err(SubscriberError::new("Subscriber doesn't exists"))
 		} else {
 			let temp = &check.unwrap();
+			Subscriber::check_suspension(redis, &temp.id.clone().unwrap())?;
 			self.id = temp.id.clone();
 		}
 		return Ok(());
@@ -136,7 +397,7 @@ impl Subscriber {
 	/* 
 	 * Adds a friend to subscriber's friend list
 	 */
-	pub fn set_friend(&mut self, redis: &DbConn, friend: Friend) -> Result<(), SubscriberError> {
+	pub fn set_friend(&mut self, redis: &DbConn, friend: Friend, if_match: Option<&String>) -> Result<String, SubscriberError> {
 		if self.id.is_none() {
 			return Err(SubscriberError::new("No Id"))
 		}
@@ -145,10 +406,14 @@ impl Subscriber {
 						].concat();
 		let check = self.check_subscriber(&redis, &friend.id);
 		if check==true {
-			match redis.hset_nx::<_,_,_,bool>(subvalue, &friend.id, &friend.name) {
-				Ok(_value) => return Ok(()),
-				Err(_e) => { return Err(SubscriberError::new("unknown error")) }
-			}
+			self.write_friends(redis, if_match, |pipe, friends| {
+				// A friend already in the list keeps their name
+				if !friends.iter().any(|known| known.id == friend.id) {
+					pipe.hset_nx(subvalue, &friend.id, &friend.name).ignore();
+					friends.push(friend.clone());
+				}
+				Ok(())
+			})
 		} else {
 			return Err(SubscriberError::new("Subscriber doesn't exist"))
 		}
@@ -157,23 +422,24 @@ impl Subscriber {
 	/* 
 	 * Delete a friend to subscriber's friend list
 	 */
-	pub fn delete_friend(&mut self, redis: &DbConn, id: String) -> Result<(), SubscriberError> {
+	pub fn delete_friend(&mut self, redis: &DbConn, id: String, if_match: Option<&String>) -> Result<String, SubscriberError> {
 		if self.id.is_none() {
 			return Err(SubscriberError::new("No Id"))
 		}
 		let subvalue = &[self.id.clone().unwrap(), 
 						 ":friends".to_string(),
 						].concat();
-		match redis.hdel::<_,_,bool>(subvalue, &id) {
-			Ok(_value) => return Ok(()),
-			Err(_e) => { return Err(SubscriberError::new("unknown error")) }
-		}
+		self.write_friends(redis, if_match, |pipe, friends| {
+			pipe.hdel(subvalue, &id).ignore();
+			friends.retain(|friend| friend.id != id);
+			Ok(())
+		})
 	}
 
 	/* 
 	 * Adds a friend to subscriber's friend list
 	 */
-	pub fn update_friend(&mut self, redis: &DbConn, friend: Friend) -> Result<(), SubscriberError> {
+	pub fn update_friend(&mut self, redis: &DbConn, friend: Friend, if_match: Option<&String>) -> Result<String, SubscriberError> {
 		if self.id.is_none() {
 			return Err(SubscriberError::new("No Id"))
 		}
@@ -191,10 +457,16 @@ impl Subscriber {
 						if val != "" {
 							name = &val;
 						}
-						match redis.hset::<_,_,_,bool>(subvalue, &friend.id, name) {
-								Ok(_value) => return Ok(()),
-								Err(_e) => { return Err(SubscriberError::new("unknown error")) }
-						}
+						self.write_friends(redis, if_match, |pipe, friends| {
+							// Removed while the name was looked up
+							let known = match friends.iter_mut().find(|known| known.id == friend.id) {
+								Some(known) => known,
+								None => return Err(SubscriberError::new("Subscriber doesn't exist"))
+							};
+							known.name = name.to_owned();
+							pipe.hset(subvalue, &friend.id, name).ignore();
+							Ok(())
+						})
 					},
 					Err(_) => {
 						return Err(SubscriberError::new("No profile"))
@@ -207,6 +479,40 @@ impl Subscriber {
 		}
 	}
 
+	/*
+	 * Changes the friends list under WATCH, so the If-Match ETag is
+	 * checked against the list that gets written. `change` queues the
+	 * write and leaves the list as it will be, returns its new ETag
+	 */
+	fn write_friends<F>(&self, redis: &DbConn, if_match: Option<&String>, mut change: F) -> Result<String, SubscriberError>
+		where F: FnMut(&mut Pipeline, &mut Vec<Friend>) -> Result<(), SubscriberError> {
+		let key = [self.id.clone().unwrap_or_default(), ":friends".to_string()].concat();
+		let mut failure: Option<SubscriberError> = None;
+		let mut tag = String::new();
+		let result = redis::transaction(&**redis, &[&key], |pipe| {
+			failure = None;
+			let mut friends = friends_from_hash(redis.hgetall::<_, Vec<(String, String)>>(&key)?);
+			if let Some(tags) = if_match {
+				if !etag::matches(tags, &etag::body(&json!(friends))) {
+					failure = Some(SubscriberError::new(etag::CHANGED));
+					return Ok(Some(()))
+				}
+			}
+			if let Err(e) = change(pipe, &mut friends) {
+				failure = Some(e);
+				return Ok(Some(()))
+			}
+			sort_friends(&mut friends);
+			tag = etag::body(&json!(friends));
+			pipe.query(&**redis)
+		});
+		match (result, failure) {
+			(Ok(()), None) => Ok(tag),
+			(Ok(()), Some(e)) => Err(e),
+			(Err(_e), _) => Err(SubscriberError::new("unknown error"))
+		}
+	}
+
 	/* 
 	 * Gets subscriber's friend list
 	 */
@@ -221,18 +527,7 @@ impl Subscriber {
 								&String,
 								Vec<(String, String)>
 							>(query) {
-				Ok(value) => {
-					let mut friends: Vec<Friend> = Vec::new();
-					for val in value {
-						let obj = Friend {
-											id: val.0.clone(),
-											name:val.1.clone(),
-										};
-						friends.push(obj);
-					}
-					return Ok(friends)
-
-				},
+				Ok(value) => return Ok(friends_from_hash(value)),
 				Err(_e) => Err(SubscriberError::new("Friends not found"))
 		}
 	}
@@ -240,16 +535,64 @@ impl Subscriber {
 	/* 
 	 * Sets the subscriber profile
 	 */
-	pub fn set_profile(&mut self, redis: &DbConn, profile: Profile) -> Result<(), SubscriberError> {
+	pub fn set_profile(&mut self, redis: &DbConn, profile: Profile, if_match: Option<&String>) -> Result<String, SubscriberError> {
 		if self.id.is_none() {
 			return Err(SubscriberError::new("No Id"))
 		}
+		profile.validate()?;
 		let subvalue = &[self.id.clone().unwrap(), 
 						 ":profile".to_string(),
 						].concat();
-		match redis.hset::<_,_,_,bool>(subvalue, "name", &profile.name) {
-			Ok(_value) => return Ok(()),
-			Err(e) => { return Err(SubscriberError::new(&e.to_string())) }
+		let id = self.id.clone().unwrap();
+		// Only what was sent is written, the rest keeps its stored value
+		let mut fields: Vec<(&str, String)> = vec![("name", profile.name.to_owned())];
+		if let Some(ref visibility) = profile.visibility {
+			fields.push(("visibility", visibility.to_owned()));
+		}
+		if let Some(ref timezone) = profile.timezone {
+			fields.push(("timezone", timezone.to_owned()));
+		}
+		if let Some(ref hemisphere) = profile.hemisphere {
+			fields.push(("hemisphere", hemisphere.to_owned()));
+		}
+		if let Some(leaderboard) = profile.leaderboard {
+			fields.push(("leaderboard", leaderboard.to_string()));
+		}
+		// Under WATCH so the If-Match ETag is checked against what gets written
+		let mut failure: Option<SubscriberError> = None;
+		let mut tag = String::new();
+		let result = redis::transaction(&**redis, &[subvalue], |pipe| {
+			failure = None;
+			let stored = redis.hgetall::<_, Vec<(String, String)>>(subvalue)?;
+			let achievements = Achievement::badges(redis, &id).unwrap_or_default();
+			if let Some(tags) = if_match {
+				// A profile that was never set has no ETag to match
+				let current = match stored.is_empty() {
+					true => None,
+					false => Some(Profile { achievements: achievements.clone(), ..Profile::from_hash(stored.clone()).with_defaults() })
+				};
+				if current.map_or(true, |current| !etag::matches(tags, &etag::body(&json!(current)))) {
+					failure = Some(SubscriberError::new(etag::CHANGED));
+					return Ok(Some(()))
+				}
+			}
+			pipe.hset_multiple(subvalue, &fields)
+				.ignore();
+			match profile.leaderboard {
+				Some(true) => { pipe.srem(Leaderboard::optout_key(), &id).ignore(); },
+				Some(false) => { pipe.sadd(Leaderboard::optout_key(), &id).ignore(); },
+				None => {}
+			}
+			// The profile as get_profile will return it after the write
+			let mut written = stored;
+			written.extend(fields.iter().map(|(field, value)| (field.to_string(), value.to_owned())));
+			tag = etag::body(&json!(Profile { achievements: achievements, ..Profile::from_hash(written).with_defaults() }));
+			pipe.query(&**redis)
+		});
+		match (result, failure) {
+			(Ok(()), None) => Ok(tag),
+			(Ok(()), Some(e)) => Err(e),
+			(Err(e), _) => Err(SubscriberError::new(&e.to_string()))
 		}
 	}
 
@@ -268,9 +611,11 @@ impl Subscriber {
 								Vec<(String, String)>
 							>(query) {
 				Ok(value) => {
-					let profile = Profile { 
-							name: value[0].1.clone()
-						};
+					if value.is_empty() {
+						return Err(SubscriberError::new("Profile not found"))
+					}
+					let mut profile = Profile::from_hash(value).with_defaults();
+					profile.achievements = Achievement::badges(redis, &self.id.clone().unwrap()).unwrap_or_default();
 					return Ok(profile);
 				},
 				Err(_e) => Err(SubscriberError::new("Profile not found"))
@@ -293,9 +638,8 @@ impl Subscriber {
 							>(query) {
 				Ok(value) => {
 					if !value.is_empty(){
-						let profile = Profile { 
-							name: value[0].1.clone()
-						};
+						let mut profile = Profile::from_hash(value).with_defaults();
+						profile.achievements = Achievement::badges(redis, id).unwrap_or_default();
 						return Ok(profile);
 					} else {
 						return Err(SubscriberError::new("Profile not found"))
@@ -326,7 +670,11 @@ impl Subscriber {
 
 		// If it doesn't exist we create an account for our subscriber
 		if check.is_none() {
-			let id = nanoid!(12);
+			// Ids of deleted subscribers stay retired
+			let mut id = nanoid!(12);
+			while let Ok(Some(_)) = redis.zscore::<_,_,Option<u64>>(Account::tombstones_key(), &id) {
+				id = nanoid!(12);
+			}
 			let subvalue = &[proto.to_owned(), 
 							 ":".to_string(), 
 							 social_id.to_owned()
@@ -342,6 +690,7 @@ impl Subscriber {
 			};
 		} else {
 			let temp = &check.unwrap();
+			Subscriber::check_suspension(&redis, &temp.id.clone().unwrap())?;
 			self.id = temp.id.clone();
 		}
 		return Ok(());
@@ -0,0 +1,233 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{self, Commands, Pipeline, PipelineCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use crate::lib::class::{Class, Item};
+use crate::lib::history::History;
+use crate::lib::leaderboard::Leaderboard;
+use crate::lib::snapshot::{self, Snapshot};
+use crate::lib::wishlist::Wishlist;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SyncRequest {
+	// The last revision the client got from the server, 0 if none
+	pub revision: u64,
+	pub changes: Vec<Item>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SyncConflict {
+	pub item: String,
+	pub client: Item,
+	// Missing when the item is gone from the server
+	pub server: Option<Item>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SyncResponse {
+	pub revision: u64,
+	// Ids of the client changes that were written
+	pub applied: Vec<String>,
+	pub conflicts: Vec<SyncConflict>,
+	// Items changed on the server since the client's revision
+	pub changes: Vec<Item>,
+}
+
+pub struct Revision;
+
+#[derive(Debug)]
+pub struct SyncError {
+	pub details: String
+}
+
+impl SyncError {
+	fn new(msg: &str) -> SyncError {
+		SyncError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for SyncError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for SyncError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+fn class_key(subscriber_id: &String, class: &String) -> String {
+	[subscriber_id.to_owned(), ":".to_string(), class.to_owned()].concat()
+}
+
+impl Revision {
+
+	/*
+	 * Current revision of every class
+	 */
+	pub(crate) fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":revisions".to_string()].concat()
+	}
+
+	/*
+	 * Revision each item of a class last changed in
+	 */
+	fn items_key(subscriber_id: &String, class: &String) -> String {
+		[subscriber_id.to_owned(), ":".to_string(), class.to_owned(), ":revisions".to_string()].concat()
+	}
+
+	pub fn get(redis: &DbConn, subscriber_id: &String, class: &String) -> RedisResult<u64> {
+		redis.hget::<_, _, Option<u64>>(Revision::key(subscriber_id), class)
+			.map(|revision| revision.unwrap_or(0))
+	}
+
+	/*
+	 * Queues a new revision of a class for the items that changed.
+	 * Writers have to WATCH the revisions key for it to be right.
+	 */
+	pub(crate) fn record(
+						redis: &DbConn,
+						query: &mut Pipeline,
+						subscriber_id: &String,
+						class: &String,
+						items: &[&String]
+					) -> RedisResult<u64> {
+		let current = Revision::get(redis, subscriber_id, class)?;
+		if items.is_empty() {
+			return Ok(current)
+		}
+		let revision = current + 1;
+		let fields: Vec<(&String, u64)> = items.iter().map(|item| (*item, revision)).collect();
+		query.hset(Revision::key(subscriber_id), class, revision)
+			.ignore()
+			.hset_multiple(Revision::items_key(subscriber_id, class), &fields)
+			.ignore();
+		Ok(revision)
+	}
+
+	/*
+	 * Items that changed after a revision
+	 */
+	fn since(redis: &DbConn, subscriber_id: &String, class: &String, revision: u64) -> RedisResult<Vec<String>> {
+		let items = redis.hgetall::<_, Vec<(String, u64)>>(Revision::items_key(subscriber_id, class))?;
+		Ok(items.into_iter()
+			.filter(|(_, changed)| *changed > revision)
+			.map(|(item, _)| item)
+			.collect())
+	}
+}
+
+/*
+ * Applies the client's changes that don't clash with newer server
+ * changes and hands back whatever the client hasn't seen yet
+ */
+pub fn sync(
+			redis: &DbConn,
+			subscriber_id: &String,
+			class: &String,
+			request: SyncRequest,
+			client: &String
+		) -> Result<SyncResponse, SyncError> {
+	if !Class(class.to_owned()).is_valid() {
+		return Err(SyncError::new("Invalid class"))
+	}
+	if let Err(e) = Leaderboard::ensure_counted(redis, subscriber_id) {
+		return Err(SyncError::new(&e.details))
+	}
+	if request.changes.len() >= snapshot::BULK_ITEMS {
+		let reason = ["a sync of ".to_string(), class.to_owned()].concat();
+		if let Err(e) = Snapshot::auto(redis, subscriber_id, &reason) {
+			return Err(SyncError::new(&e.details))
+		}
+	}
+	// The last value of an item wins, same as in post_class
+	let mut latest: HashMap<String, Item> = HashMap::new();
+	for item in request.changes {
+		latest.insert(item.id.to_owned(), Item { class: class.to_owned(), ..item });
+	}
+	let ids: Vec<&String> = latest.keys().collect();
+	let key = class_key(subscriber_id, class);
+	let keys = [key.to_owned(), Revision::key(subscriber_id), Revision::items_key(subscriber_id, class)];
+	let mut response = SyncResponse { revision: 0, applied: Vec::new(), conflicts: Vec::new(), changes: Vec::new() };
+	let mut failure: Option<String> = None;
+	let result = redis::transaction(&**redis, &keys, |pipe| {
+		failure = None;
+		response.applied.clear();
+		response.conflicts.clear();
+		let revision = Revision::get(redis, subscriber_id, class)?;
+		if request.revision > revision {
+			failure = Some("Invalid revision".to_string());
+			return Ok(Some(()))
+		}
+		let newer = Revision::since(redis, subscriber_id, class, request.revision)?;
+		let current = if ids.is_empty() {
+			Vec::new()
+		} else {
+			redis::cmd("HMGET").arg(&key).arg(&ids).query::<Vec<Option<String>>>(&**redis)?
+		};
+		let mut changed: Vec<&String> = Vec::new();
+		let mut writes: Vec<(&String, String)> = Vec::new();
+		let mut delta: i64 = 0;
+		for (id, meta) in ids.iter().zip(current) {
+			let old = meta.and_then(|meta| Item::from_meta(class, id, &meta));
+			let new = &latest[*id];
+			let same = old.as_ref().map_or(false, |old| old.donated == new.donated && old.stock == new.stock);
+			if same {
+				continue;
+			}
+			// Changed on the server after the client last synced
+			if newer.contains(*id) {
+				response.conflicts.push(SyncConflict { item: id.to_string(), client: new.clone(), server: old });
+				continue;
+			}
+			match (old.as_ref().map_or(false, |old| old.donated), new.donated) {
+				(false, true) => delta += 1,
+				(true, false) => delta -= 1,
+				_ => {}
+			}
+			History::record(pipe, subscriber_id, &old, new, client);
+			writes.push((*id, [new.donated.to_string(), ":".to_string(), new.stock.to_string()].concat()));
+			changed.push(*id);
+		}
+		if !writes.is_empty() {
+			pipe.hset_multiple(&key, &writes).ignore();
+			Class::index(pipe, subscriber_id, class);
+		}
+		Leaderboard::record(pipe, subscriber_id, class, delta);
+		response.revision = Revision::record(redis, pipe, subscriber_id, class, &changed)?;
+		response.applied = changed.iter().map(|id| id.to_string()).collect();
+		pipe.query(&**redis)
+	});
+	match (result, failure) {
+		(Ok(()), None) => {},
+		(Ok(()), Some(details)) => return Err(SyncError::new(&details)),
+		(Err(_e), _) => return Err(SyncError::new("Sync failed"))
+	}
+	let applied: Vec<Item> = response.applied.iter().map(|id| latest[id].clone()).collect();
+	if let Err(e) = Wishlist::fulfil(redis, subscriber_id, class, &applied) {
+		return Err(SyncError::new(&e.details))
+	}
+	// Whatever the client is missing, including the server side of conflicts
+	let missing: Vec<String> = match Revision::since(redis, subscriber_id, class, request.revision) {
+		Ok(items) => items.into_iter().filter(|item| !response.applied.contains(item)).collect(),
+		Err(_e) => return Err(SyncError::new("Changes not found"))
+	};
+	if !missing.is_empty() {
+		let values = match redis::cmd("HMGET").arg(&key).arg(&missing).query::<Vec<Option<String>>>(&**redis) {
+			Ok(values) => values,
+			Err(_e) => return Err(SyncError::new("Changes not found"))
+		};
+		// Items removed by a restore come back empty
+		response.changes = missing.iter()
+			.zip(values)
+			.map(|(id, meta)| meta.and_then(|meta| Item::from_meta(class, id, &meta))
+								.unwrap_or(Item { class: class.to_owned(), id: id.to_owned(), donated: false, stock: 0 }))
+			.collect();
+	}
+	Ok(response)
+}
@@ -0,0 +1,479 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{self, Commands, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::error;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+use nanoid::nanoid;
+
+use crate::lib::subscriber::{Subscriber, Friend};
+use crate::lib::class::{Class, Item};
+use crate::lib::history::History;
+use crate::lib::sync::Revision;
+use crate::lib::wishlist::Wishlist;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TradeSuggestion {
+	pub friend: Friend,
+	pub gives: Vec<Item>,
+	pub receives: Vec<Item>,
+	pub score: u32
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TradeItem {
+	pub class: String,
+	pub id: String,
+	pub quantity: u32
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Trade {
+	pub id: Option<String>,
+	pub from: Option<String>,
+	pub to: String,
+	pub offered: Vec<TradeItem>,
+	pub requested: Vec<TradeItem>,
+	pub state: Option<String>,
+	pub created: Option<u64>,
+	pub updated: Option<u64>
+}
+
+#[derive(Debug)]
+pub struct TradeError {
+	pub details: String
+}
+
+impl TradeError {
+	fn new(msg: &str) -> TradeError {
+		TradeError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for TradeError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for TradeError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+pub fn now() -> u64 {
+	match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(duration) => duration.as_secs(),
+		Err(_e) => 0
+	}
+}
+
+/*
+ * Items from `from` that `to` is still missing, the stock
+ * of every returned item is what `from` can spare
+ */
+fn matching_items(from: &HashMap<String, Item>, to: &HashMap<String, Item>) -> Vec<Item> {
+	let mut items: Vec<Item> = Vec::new();
+	for (id, item) in from {
+		let spare = item.spare();
+		if spare == 0 {
+			continue;
+		}
+		let needed = match to.get(id) {
+			Some(other) => other.needed(),
+			None => true
+		};
+		if needed {
+			let mut obj = item.clone();
+			obj.stock = spare;
+			items.push(obj);
+		}
+	}
+	items
+}
+
+impl TradeSuggestion {
+
+	/*
+	 * Computes the trades a subscriber could make with each friend
+	 * whose inventory they can see. Every class either of them has
+	 * is compared, one that was never synced has nothing donated
+	 */
+	pub fn for_subscriber(redis: &DbConn, subscriber: &mut Subscriber) -> Result<Vec<TradeSuggestion>, TradeError> {
+		if subscriber.id.is_none() {
+			return Err(TradeError::new("No Id"))
+		}
+		let id = subscriber.id.clone().unwrap();
+		let friends = match subscriber.get_friends(redis) {
+			Ok(friends) => friends,
+			Err(e) => return Err(TradeError::new(&e.details))
+		};
+		// Private and friends only inventories aren't compared against
+		let friends = match subscriber.visible_friends(redis, friends) {
+			Ok(friends) => friends,
+			Err(e) => return Err(TradeError::new(&e.details))
+		};
+		let mine: HashMap<String, HashMap<String, Item>> = match Class::get_all(redis, &id) {
+			Ok(classes) => classes.into_iter()
+								.map(|(class, items)| (class, items.into_iter().map(|item| (item.id.to_owned(), item)).collect()))
+								.collect(),
+			Err(e) => return Err(TradeError::new(&e.details))
+		};
+		let ids: Vec<String> = friends.iter().map(|friend| friend.id.to_owned()).collect();
+		let friend_classes = match Class::list_classes_of(redis, &ids) {
+			Ok(classes) => classes,
+			Err(e) => return Err(TradeError::new(&e.details))
+		};
+		// Every class of every friend, in one go
+		let mut fetched: Vec<(usize, String)> = Vec::new();
+		let mut query = redis::pipe();
+		for (index, classes) in friend_classes.into_iter().enumerate() {
+			for class in classes {
+				query.hgetall([ids[index].to_owned(), ":".to_string(), class.to_owned()].concat());
+				fetched.push((index, class));
+			}
+		}
+		let values: Vec<Vec<(String, String)>> = if fetched.is_empty() {
+			Vec::new()
+		} else {
+			match query.query(&**redis) {
+				Ok(values) => values,
+				Err(_e) => return Err(TradeError::new("Items not found"))
+			}
+		};
+		let mut theirs: Vec<HashMap<String, HashMap<String, Item>>> = friends.iter().map(|_| HashMap::new()).collect();
+		for ((index, class), value) in fetched.into_iter().zip(values) {
+			let their_items: HashMap<String, Item> = value.iter()
+				.filter_map(|(item, meta)| Item::from_meta(&class, item, meta))
+				.map(|item| (item.id.to_owned(), item))
+				.collect();
+			theirs[index].insert(class, their_items);
+		}
+		let empty: HashMap<String, Item> = HashMap::new();
+		let mut gives: Vec<Vec<Item>> = friends.iter().map(|_| Vec::new()).collect();
+		let mut receives: Vec<Vec<Item>> = friends.iter().map(|_| Vec::new()).collect();
+		for (index, their_classes) in theirs.iter().enumerate() {
+			let classes: BTreeSet<&String> = their_classes.keys().chain(mine.keys()).collect();
+			for class in classes {
+				let my_items = mine.get(class).unwrap_or(&empty);
+				let their_items = their_classes.get(class).unwrap_or(&empty);
+				gives[index].extend(matching_items(my_items, their_items));
+				receives[index].extend(matching_items(their_items, my_items));
+			}
+		}
+
+		let mut suggestions: Vec<TradeSuggestion> = Vec::new();
+		for ((friend, gives), receives) in friends.into_iter().zip(gives).zip(receives) {
+			if gives.is_empty() && receives.is_empty() {
+				continue;
+			}
+			// Balanced swaps first, then anything one sided
+			let score = std::cmp::min(gives.len(), receives.len()) as u32;
+			suggestions.push(TradeSuggestion {
+									friend: friend,
+									gives: gives,
+									receives: receives,
+									score: score
+								});
+		}
+		suggestions.sort_by(|a, b| {
+			b.score.cmp(&a.score)
+				.then((b.gives.len() + b.receives.len()).cmp(&(a.gives.len() + a.receives.len())))
+		});
+		Ok(suggestions)
+	}
+}
+
+impl Trade {
+
+	fn key(id: &String) -> String {
+		["trade:".to_string(), id.to_owned()].concat()
+	}
+
+	/*
+	 * Gets a trade, only the parties involved can see it
+	 */
+	pub fn get(redis: &DbConn, subscriber_id: &String, id: &String) -> Result<Trade, TradeError> {
+		match redis.hgetall::<_, HashMap<String, String>>(Trade::key(id)) {
+			Ok(value) => {
+				if value.is_empty() {
+					return Err(TradeError::new("Trade not found"))
+				}
+				let field = |name: &str| value.get(name).cloned().unwrap_or_default();
+				let trade = Trade {
+					id: Some(id.to_owned()),
+					from: Some(field("from")),
+					to: field("to"),
+					offered: serde_json::from_str(&field("offered")).unwrap_or_default(),
+					requested: serde_json::from_str(&field("requested")).unwrap_or_default(),
+					state: Some(field("state")),
+					created: field("created").parse().ok(),
+					updated: field("updated").parse().ok()
+				};
+				if trade.from.as_ref() != Some(subscriber_id) && &trade.to != subscriber_id {
+					return Err(TradeError::new("Trade not found"))
+				}
+				return Ok(trade)
+			},
+			Err(_e) => Err(TradeError::new("Trade not found"))
+		}
+	}
+
+	/*
+	 * Lists the trades a subscriber is part of, newest first
+	 */
+	pub fn list(redis: &DbConn, subscriber_id: &String) -> Result<Vec<Trade>, TradeError> {
+		let query = &[subscriber_id.to_owned(), 
+					  ":trades".to_string(),
+					].concat();
+		match redis.zrevrange::<_, Vec<String>>(query, 0, -1) {
+			Ok(ids) => {
+				let mut trades: Vec<Trade> = Vec::new();
+				for id in ids {
+					if let Ok(trade) = Trade::get(redis, subscriber_id, &id) {
+						trades.push(trade);
+					}
+				}
+				return Ok(trades)
+			},
+			Err(_e) => Err(TradeError::new("Trades not found"))
+		}
+	}
+
+	/*
+	 * Proposes a trade to a friend
+	 */
+	pub fn create(&mut self, redis: &DbConn, subscriber: &mut Subscriber) -> Result<(), TradeError> {
+		if subscriber.id.is_none() {
+			return Err(TradeError::new("No Id"))
+		}
+		let from = subscriber.id.clone().unwrap();
+		if from == self.to {
+			return Err(TradeError::new("Can't trade with yourself"))
+		}
+		if subscriber.check_friend(redis, &self.to).is_err() {
+			return Err(TradeError::new("Not in friends list"))
+		}
+		if self.offered.is_empty() && self.requested.is_empty() {
+			return Err(TradeError::new("Empty trade"))
+		}
+		for item in self.offered.iter().chain(self.requested.iter()) {
+			if item.quantity == 0 || !Class(item.class.to_owned()).is_valid() {
+				return Err(TradeError::new("Invalid item"))
+			}
+		}
+		let id = nanoid!(12);
+		let timestamp = now();
+		let offered = serde_json::to_string(&self.offered).unwrap();
+		let requested = serde_json::to_string(&self.requested).unwrap();
+		let from_trades = &[from.to_owned(), ":trades".to_string()].concat();
+		let to_trades = &[self.to.to_owned(), ":trades".to_string()].concat();
+		match redis::pipe()
+				.atomic()
+				.hset_multiple(Trade::key(&id), &[
+									("from", from.to_owned()),
+									("to", self.to.to_owned()),
+									("offered", offered),
+									("requested", requested),
+									("state", "pending".to_string()),
+									("created", timestamp.to_string()),
+									("updated", timestamp.to_string()),
+								])
+				.ignore()
+				.zadd(from_trades, &id, timestamp)
+				.ignore()
+				.zadd(to_trades, &id, timestamp)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => {
+				self.id = Some(id);
+				self.from = Some(from);
+				self.state = Some("pending".to_string());
+				self.created = Some(timestamp);
+				self.updated = Some(timestamp);
+				return Ok(())
+			},
+			Err(_e) => Err(TradeError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Cancels the open trades of a subscriber who is leaving,
+	 * so nothing gets written to them afterwards
+	 */
+	pub(crate) fn abandon(redis: &DbConn, subscriber_id: &String) -> Result<(), TradeError> {
+		let timestamp = now();
+		for trade in Trade::list(redis, subscriber_id)? {
+			match trade.state.as_ref().map(|state| state.as_str()) {
+				Some("pending") | Some("accepted") => {},
+				_ => continue
+			}
+			// A trade completed in the meantime stays completed
+			Trade::transition(redis, &trade.id.unwrap(), &["pending", "accepted"], "cancelled", timestamp)?;
+		}
+		Ok(())
+	}
+
+	/*
+	 * Sets the state if it's still one of the expected ones, WATCH
+	 * keeps two parties acting at once from both getting through.
+	 * Returns false when the trade moved on in the meantime
+	 */
+	fn transition(redis: &DbConn, id: &String, expected: &[&str], next: &str, timestamp: u64) -> Result<bool, TradeError> {
+		let key = Trade::key(id);
+		let mut moved = false;
+		let result = redis::transaction(&**redis, &[&key], |pipe| {
+			moved = false;
+			match redis.hget::<_, _, Option<String>>(&key, "state")? {
+				Some(ref state) if expected.contains(&state.as_str()) => {},
+				_ => {
+					moved = true;
+					return Ok(Some(()))
+				}
+			}
+			pipe.hset_multiple(&key, &[
+									("state", next.to_string()),
+									("updated", timestamp.to_string()),
+								])
+				.ignore()
+				.query(&**redis)
+		});
+		match result {
+			Ok(()) => Ok(!moved),
+			Err(_e) => Err(TradeError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Moves the trade to a new state, the recipient accepts or declines,
+	 * the proposer cancels and either of them completes it
+	 */
+	pub fn update_state(&mut self, redis: &DbConn, subscriber_id: &String, action: &str) -> Result<(), TradeError> {
+		let id = match self.id.clone() {
+			Some(id) => id,
+			None => return Err(TradeError::new("No Id"))
+		};
+		let is_from = self.from.as_ref() == Some(subscriber_id);
+		let is_to = &self.to == subscriber_id;
+		let state = self.state.clone().unwrap_or_default();
+		let next = match (action, state.as_str()) {
+			("accept", "pending") if is_to => "accepted",
+			("decline", "pending") if is_to => "declined",
+			("cancel", "pending") | ("cancel", "accepted") if is_from => "cancelled",
+			("complete", "accepted") if is_from || is_to => return self.complete(redis),
+			_ => return Err(TradeError::new("Invalid action"))
+		};
+		let timestamp = now();
+		if !Trade::transition(redis, &id, &[state.as_str()], next, timestamp)? {
+			return Err(TradeError::new("Trade has changed"))
+		}
+		self.state = Some(next.to_string());
+		self.updated = Some(timestamp);
+		Ok(())
+	}
+
+	/*
+	 * Swaps the stock of both parties in a single transaction,
+	 * if either side doesn't have the stock anymore nothing changes
+	 */
+	fn complete(&mut self, redis: &DbConn) -> Result<(), TradeError> {
+		let id = self.id.clone().unwrap();
+		let from = self.from.clone().unwrap();
+		let to = self.to.clone();
+
+		// (owner, class, item) -> stock change
+		let mut deltas: HashMap<(String, String, String), i64> = HashMap::new();
+		for item in &self.offered {
+			*deltas.entry((from.to_owned(), item.class.to_owned(), item.id.to_owned())).or_insert(0) -= item.quantity as i64;
+			*deltas.entry((to.to_owned(), item.class.to_owned(), item.id.to_owned())).or_insert(0) += item.quantity as i64;
+		}
+		for item in &self.requested {
+			*deltas.entry((to.to_owned(), item.class.to_owned(), item.id.to_owned())).or_insert(0) -= item.quantity as i64;
+			*deltas.entry((from.to_owned(), item.class.to_owned(), item.id.to_owned())).or_insert(0) += item.quantity as i64;
+		}
+		let mut keys: Vec<String> = deltas.keys()
+			.map(|(owner, class, _)| [owner.to_owned(), ":".to_string(), class.to_owned()].concat())
+			.collect();
+		keys.sort();
+		keys.dedup();
+		keys.push(Trade::key(&id));
+		keys.push(Revision::key(&from));
+		keys.push(Revision::key(&to));
+
+		let timestamp = now();
+		let client = ["trade:".to_string(), id.to_owned()].concat();
+		let mut failure: Option<String> = None;
+		let mut received: HashMap<(String, String), Vec<Item>> = HashMap::new();
+		let result = redis::transaction(&**redis, &keys, |pipe| {
+			failure = None;
+			received.clear();
+			match redis.hget::<_, _, Option<String>>(Trade::key(&id), "state") {
+				Ok(Some(ref state)) if state == "accepted" => {},
+				_ => {
+					failure = Some("Trade is not accepted".to_string());
+					return Ok(Some(()))
+				}
+			}
+			let mut changed: HashMap<(&String, &String), Vec<&String>> = HashMap::new();
+			for ((owner, class, item), delta) in &deltas {
+				let query = [owner.to_owned(), ":".to_string(), class.to_owned()].concat();
+				let old = redis.hget::<_, _, Option<String>>(&query, item)?
+					.and_then(|meta| Item::from_meta(class, item, &meta));
+				let current = old.clone().unwrap_or(Item {
+									class: class.to_owned(),
+									id: item.to_owned(),
+									donated: false,
+									stock: 0,
+								});
+				let stock = current.stock as i64 + delta;
+				if stock < 0 {
+					failure = Some(["Not enough stock of ".to_string(), item.to_owned()].concat());
+					return Ok(Some(()))
+				}
+				let meta = [current.donated.to_string(), 
+							":".to_string(), 
+							stock.to_string()
+							].concat();
+				pipe.hset(&query, item, meta).ignore();
+				Class::index(pipe, owner, class);
+				let new = Item { stock: stock as u32, ..current };
+				if *delta > 0 {
+					received.entry((owner.to_owned(), class.to_owned())).or_insert(Vec::new()).push(new.clone());
+				}
+				if *delta != 0 {
+					History::record(pipe, owner, &old, &new, &client);
+					changed.entry((owner, class)).or_insert(Vec::new()).push(item);
+				}
+			}
+			for ((owner, class), items) in &changed {
+				Revision::record(redis, pipe, owner, class, items)?;
+			}
+			pipe.hset_multiple(Trade::key(&id), &[
+									("state", "completed".to_string()),
+									("updated", timestamp.to_string()),
+								])
+				.ignore()
+				.zadd([from.to_owned(), ":trades:history".to_string()].concat(), &id, timestamp)
+				.ignore()
+				.zadd([to.to_owned(), ":trades:history".to_string()].concat(), &id, timestamp)
+				.ignore()
+				.query(&**redis)
+		});
+		match (result, failure) {
+			(Ok(()), None) => {
+				self.state = Some("completed".to_string());
+				self.updated = Some(timestamp);
+				// What arrived in stock leaves the wishlist. The trade is done
+				// by now, a wishlist that couldn't be cleared doesn't undo it
+				for ((owner, class), items) in &received {
+					let _ = Wishlist::fulfil(redis, owner, class, items);
+				}
+				return Ok(())
+			},
+			(Ok(()), Some(details)) => Err(TradeError::new(&details)),
+			(Err(_e), _) => Err(TradeError::new("unknown error"))
+		}
+	}
+}
\ No newline at end of file
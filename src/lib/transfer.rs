@@ -0,0 +1,474 @@
+use crate::DbConn;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+
+use crate::lib::class::{Class, Item};
+use crate::lib::subscriber::{Subscriber, Friend, Profile};
+
+pub const FORMATS: &[&str] = &["json", "csv"];
+const CSV_HEADER: &str = "kind,class,id,donated,stock,value";
+
+// Category names the community spreadsheets use for our classes
+const CLASS_ALIASES: &[(&str, &str)] = &[
+	("insects", "bugs"),
+	("bug", "bugs"),
+	("sea_creatures", "sea"),
+	("deep_sea_creatures", "sea"),
+	("deep_sea", "sea"),
+	("fossil", "fossils"),
+	("artwork", "art"),
+	("diy_recipes", "recipes"),
+];
+
+/*
+ * Everything a subscriber keeps, as exported and imported
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Export {
+	pub profile: Option<Profile>,
+	#[serde(default)]
+	pub friends: Vec<Friend>,
+	#[serde(default)]
+	pub classes: HashMap<String, Vec<Item>>,
+	// Spreadsheets without a donated or stock column keep the stored values
+	#[serde(skip)]
+	pub keep_donated: bool,
+	#[serde(skip)]
+	pub keep_stock: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClassReport {
+	pub class: String,
+	pub added: u32,
+	pub changed: u32,
+	pub unchanged: u32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImportChange {
+	pub class: String,
+	pub item: String,
+	// Missing when the item is new
+	pub old: Option<Item>,
+	pub new: Item,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+	// Nothing was written, this is what would have changed
+	pub dry_run: bool,
+	pub classes: Vec<ClassReport>,
+	pub changes: Vec<ImportChange>,
+	// Ids of the friends added, friends already in the list keep their name
+	pub friends: Vec<String>,
+	pub profile: bool,
+	// Lines and entries that couldn't be used, with the reason
+	pub skipped: Vec<String>,
+	// Classes that made it in, all of them unless there's an error
+	pub written: Vec<String>,
+	// Why the import stopped, nothing after the written classes was applied
+	pub error: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct TransferError {
+	pub details: String
+}
+
+impl TransferError {
+	fn new(msg: &str) -> TransferError {
+		TransferError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for TransferError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for TransferError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+/*
+ * Quotes a CSV field when it needs it
+ */
+pub fn csv_field(value: &str) -> String {
+	if value.contains(',') || value.contains('"') || value.contains('\n') || value.contains('\r') {
+		["\"".to_string(), value.replace("\"", "\"\""), "\"".to_string()].concat()
+	} else {
+		value.to_string()
+	}
+}
+
+/*
+ * Splits CSV text into records, quoted fields can hold commas,
+ * quotes and line breaks
+ */
+pub fn csv_records(text: &str) -> Vec<Vec<String>> {
+	let mut records: Vec<Vec<String>> = Vec::new();
+	let mut record: Vec<String> = Vec::new();
+	let mut field = String::new();
+	let mut quoted = false;
+	let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+	while let Some(c) = chars.next() {
+		match (quoted, c) {
+			(true, '"') if chars.peek() == Some(&'"') => {
+				field.push('"');
+				chars.next();
+			},
+			(true, '"') => quoted = false,
+			(true, c) => field.push(c),
+			(false, '"') => quoted = true,
+			(false, ',') => record.push(std::mem::replace(&mut field, String::new())),
+			(false, '\r') => {},
+			(false, '\n') => {
+				record.push(std::mem::replace(&mut field, String::new()));
+				records.push(std::mem::replace(&mut record, Vec::new()));
+			},
+			(false, c) => field.push(c),
+		}
+	}
+	if !field.is_empty() || !record.is_empty() {
+		record.push(field);
+		records.push(record);
+	}
+	records.into_iter()
+		.filter(|record| record.iter().any(|field| !field.trim().is_empty()))
+		.collect()
+}
+
+/*
+ * Spreadsheet names to our ids, "Sea Bass" is "sea-bass"
+ */
+pub fn slug(name: &str, separator: char) -> String {
+	let mut slug = String::new();
+	for c in name.trim().to_lowercase().chars() {
+		if c.is_alphanumeric() {
+			slug.push(c);
+		} else if (c == ' ' || c == '-' || c == '_') && !slug.is_empty() && !slug.ends_with(separator) {
+			slug.push(separator);
+		}
+	}
+	slug.trim_end_matches(separator).to_string()
+}
+
+pub fn class_name(category: &str) -> String {
+	let class = slug(category, '_');
+	CLASS_ALIASES.iter()
+		.find(|(alias, _)| *alias == class)
+		.map_or(class.to_owned(), |(_, class)| class.to_string())
+}
+
+/*
+ * Ticks, crosses and words people use for "donated"
+ */
+fn truthy(value: &str) -> bool {
+	match value.trim().to_lowercase().as_str() {
+		"true" | "yes" | "y" | "x" | "1" | "✓" | "✔" | "donated" => true,
+		_ => false
+	}
+}
+
+impl Export {
+
+	/*
+	 * Gathers the profile, friends and every class of the subscriber
+	 */
+	pub fn get(redis: &DbConn, subscriber: &mut Subscriber) -> Result<Export, TransferError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(TransferError::new("No Id"))
+		};
+		let friends = match subscriber.get_friends(redis) {
+			Ok(friends) => friends,
+			Err(e) => return Err(TransferError::new(&e.details))
+		};
+		let classes = match Class::get_all(redis, &subscriber_id) {
+			Ok(classes) => classes,
+			Err(e) => return Err(TransferError::new(&e.details))
+		};
+		Ok(Export {
+			profile: subscriber.get_profile(redis).ok(),
+			friends: friends,
+			classes: classes,
+			keep_donated: false,
+			keep_stock: false
+		})
+	}
+
+	/*
+	 * One row per item, friend and profile field
+	 */
+	pub fn to_csv(&self) -> String {
+		let mut rows: Vec<String> = vec![CSV_HEADER.to_string()];
+		let row = |fields: &[&str]| fields.iter().map(|field| csv_field(field)).collect::<Vec<String>>().join(",");
+		if let Some(ref profile) = self.profile {
+			let leaderboard = profile.leaderboard.map(|leaderboard| leaderboard.to_string());
+			for (field, value) in &[
+									("name", Some(&profile.name)),
+									("visibility", profile.visibility.as_ref()),
+									("timezone", profile.timezone.as_ref()),
+									("hemisphere", profile.hemisphere.as_ref()),
+									("leaderboard", leaderboard.as_ref()),
+								] {
+				if let Some(value) = value {
+					rows.push(row(&["profile", "", *field, "", "", value.as_str()]));
+				}
+			}
+		}
+		let mut friends: Vec<&Friend> = self.friends.iter().collect();
+		friends.sort_by(|a, b| a.id.cmp(&b.id));
+		for friend in friends {
+			rows.push(row(&["friend", "", friend.id.as_str(), "", "", friend.name.as_str()]));
+		}
+		let mut classes: Vec<&String> = self.classes.keys().collect();
+		classes.sort();
+		for class in classes {
+			let mut items: Vec<&Item> = self.classes[class].iter().collect();
+			items.sort_by(|a, b| a.id.cmp(&b.id));
+			for item in items {
+				let (donated, stock) = (item.donated.to_string(), item.stock.to_string());
+				rows.push(row(&["item", class.as_str(), item.id.as_str(), donated.as_str(), stock.as_str(), ""]));
+			}
+		}
+		rows.join("\r\n") + "\r\n"
+	}
+
+	/*
+	 * Reads our own CSV layout or a community spreadsheet, which
+	 * only has items. Returns the rows that were skipped too.
+	 */
+	pub fn from_csv(text: &str) -> Result<(Export, Vec<String>), TransferError> {
+		let records = csv_records(text);
+		let header: Vec<String> = match records.first() {
+			Some(header) => header.iter().map(|column| slug(column, '_')).collect(),
+			None => return Err(TransferError::new("Empty file"))
+		};
+		let column = |names: &[&str]| header.iter().position(|column| names.contains(&column.as_str()));
+		let native = header.first().map_or(false, |column| column == "kind");
+		let (kind, class, id, donated, stock, value) = if native {
+			(Some(0), column(&["class"]), column(&["id"]), column(&["donated"]), column(&["stock"]), column(&["value"]))
+		} else {
+			(
+				None,
+				column(&["category", "class", "type"]),
+				column(&["name", "item", "id"]),
+				column(&["donated", "museum"]),
+				column(&["quantity", "stock", "owned", "qty"]),
+				None
+			)
+		};
+		let (class, id) = match (class, id) {
+			(Some(class), Some(id)) => (class, id),
+			_ => return Err(TransferError::new("Unknown columns"))
+		};
+		let mut export = Export {
+			profile: None,
+			friends: Vec::new(),
+			classes: HashMap::new(),
+			keep_donated: donated.is_none(),
+			keep_stock: stock.is_none()
+		};
+		let mut profile: Vec<(String, String)> = Vec::new();
+		let mut skipped: Vec<String> = Vec::new();
+		for (line, record) in records.iter().enumerate().skip(1) {
+			let field = |index: Option<usize>| index
+				.and_then(|index| record.get(index))
+				.map_or("", |field| field.trim());
+			let line = (line + 1).to_string();
+			match field(kind) {
+				"profile" => profile.push((field(Some(id)).to_string(), field(value).to_string())),
+				"friend" if !field(Some(id)).is_empty() => export.friends.push(Friend {
+																id: field(Some(id)).to_string(),
+																name: field(value).to_string()
+															}),
+				"item" | "" => {
+					let (item_class, item_id) = if native {
+						(field(Some(class)).to_string(), field(Some(id)).to_string())
+					} else {
+						(class_name(field(Some(class))), slug(field(Some(id)), '-'))
+					};
+					let stock = match field(stock) {
+						"" => Ok(0),
+						stock => stock.parse::<u32>()
+					};
+					match stock {
+						Ok(stock) if !item_class.is_empty() && !item_id.is_empty() => {
+							export.classes.entry(item_class.to_owned()).or_insert(Vec::new()).push(Item {
+																		class: item_class,
+																		id: item_id,
+																		donated: truthy(field(donated)),
+																		stock: stock,
+																	});
+						},
+						Ok(_) => skipped.push(["line ".to_string(), line, ": missing class or item".to_string()].concat()),
+						Err(_e) => skipped.push(["line ".to_string(), line, ": invalid stock".to_string()].concat()),
+					}
+				},
+				_ => skipped.push(["line ".to_string(), line, ": unknown kind".to_string()].concat())
+			}
+		}
+		if !profile.is_empty() {
+			export.profile = Some(Profile::from_hash(profile));
+		}
+		Ok((export, skipped))
+	}
+
+	pub fn from_json(text: &str) -> Result<(Export, Vec<String>), TransferError> {
+		match serde_json::from_str::<Export>(text) {
+			Ok(export) => Ok((export, Vec::new())),
+			Err(e) => Err(TransferError::new(&["Invalid JSON: ".to_string(), e.to_string()].concat()))
+		}
+	}
+
+	/*
+	 * Writes the items that differ through Class::set_class, adds the
+	 * friends that are missing and sets the profile fields the file has.
+	 * Everything is checked first, a write that fails stops the import
+	 * and the report says which classes were written. A dry run only
+	 * reports what would change.
+	 */
+	pub fn import(
+					self,
+					redis: &DbConn,
+					subscriber: &mut Subscriber,
+					mut skipped: Vec<String>,
+					dry_run: bool,
+					client: &String
+				) -> Result<ImportReport, TransferError> {
+		let subscriber_id = match subscriber.id.clone() {
+			Some(id) => id,
+			None => return Err(TransferError::new("No Id"))
+		};
+		let current = match Class::get_all(redis, &subscriber_id) {
+			Ok(current) => current,
+			Err(e) => return Err(TransferError::new(&e.details))
+		};
+		let mut report = ImportReport {
+			dry_run: dry_run,
+			classes: Vec::new(),
+			changes: Vec::new(),
+			friends: Vec::new(),
+			profile: false,
+			skipped: Vec::new(),
+			written: Vec::new(),
+			error: None,
+		};
+		let mut classes: Vec<(String, Vec<Item>)> = self.classes.into_iter().collect();
+		classes.sort_by(|a, b| a.0.cmp(&b.0));
+		let empty: Vec<Item> = Vec::new();
+		// Everything is checked before anything is written
+		let mut pending: Vec<(Class, Vec<Item>)> = Vec::new();
+		for (name, items) in classes {
+			let class = Class(name.to_owned());
+			if !class.is_valid() {
+				skipped.push(["class ".to_string(), name, ": invalid class".to_string()].concat());
+				continue;
+			}
+			let existing: HashMap<&String, &Item> = current.get(&name).unwrap_or(&empty).iter()
+				.map(|item| (&item.id, item))
+				.collect();
+			// The last row of an item wins
+			let mut latest: HashMap<String, Item> = HashMap::new();
+			for item in items {
+				let old = existing.get(&item.id);
+				let item = Item {
+					class: name.to_owned(),
+					donated: if self.keep_donated { old.map_or(false, |old| old.donated) } else { item.donated },
+					stock: if self.keep_stock { old.map_or(0, |old| old.stock) } else { item.stock },
+					..item
+				};
+				latest.insert(item.id.to_owned(), item);
+			}
+			let mut ids: Vec<&String> = latest.keys().collect();
+			ids.sort();
+			let mut summary = ClassReport { class: name.to_owned(), added: 0, changed: 0, unchanged: 0 };
+			let mut writes: Vec<Item> = Vec::new();
+			for id in ids {
+				let new = &latest[id];
+				match existing.get(id) {
+					Some(old) if old.donated == new.donated && old.stock == new.stock => {
+						summary.unchanged += 1;
+						continue;
+					},
+					Some(_) => summary.changed += 1,
+					None => summary.added += 1
+				}
+				report.changes.push(ImportChange {
+					class: name.to_owned(),
+					item: id.to_owned(),
+					old: existing.get(id).map(|old| (*old).clone()),
+					new: new.clone()
+				});
+				writes.push(new.clone());
+			}
+			if !writes.is_empty() {
+				pending.push((class, writes));
+			}
+			report.classes.push(summary);
+		}
+		let known = match subscriber.get_friends(redis) {
+			Ok(friends) => friends,
+			Err(e) => return Err(TransferError::new(&e.details))
+		};
+		let mut friends: Vec<Friend> = Vec::new();
+		for friend in self.friends {
+			if friend.id == subscriber_id
+				|| known.iter().any(|known| known.id == friend.id)
+				|| friends.iter().any(|added| added.id == friend.id) {
+				continue;
+			}
+			if !subscriber.check_subscriber(redis, &friend.id) {
+				skipped.push(["friend ".to_string(), friend.id, ": subscriber doesn't exist".to_string()].concat());
+				continue;
+			}
+			report.friends.push(friend.id.to_owned());
+			friends.push(friend);
+		}
+		let mut profile = self.profile;
+		if let Some(ref mut new) = profile {
+			let old = subscriber.get_profile(redis).ok();
+			// A file without a name row keeps the stored name
+			if new.name.is_empty() {
+				new.name = old.as_ref().map_or(String::new(), |old| old.name.to_owned());
+			}
+			report.profile = old.map_or(true, |old| new.changes(&old));
+		}
+		if report.profile {
+			if let Some(Err(e)) = profile.as_ref().map(|profile| profile.validate()) {
+				skipped.push(["profile: ".to_string(), e.details].concat());
+				report.profile = false;
+			}
+		}
+		report.skipped = skipped;
+		if dry_run {
+			return Ok(report)
+		}
+		for (mut class, writes) in pending {
+			if let Err(e) = class.set_class(redis, &subscriber_id, writes, client, None) {
+				report.error = Some([class.0, ": ".to_string(), e.details].concat());
+				return Ok(report)
+			}
+			report.written.push(class.0);
+		}
+		for friend in friends {
+			if let Err(e) = subscriber.set_friend(redis, friend, None) {
+				report.error = Some(["friends: ".to_string(), e.details].concat());
+				return Ok(report)
+			}
+		}
+		if let (true, Some(profile)) = (report.profile, profile) {
+			if let Err(e) = subscriber.set_profile(redis, profile, None) {
+				report.error = Some(["profile: ".to_string(), e.details].concat());
+			}
+		}
+		Ok(report)
+	}
+}
@@ -0,0 +1,323 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use chrono::{Datelike, Duration, NaiveDate, Timelike, Utc, Weekday};
+use chrono_tz::Tz;
+
+use crate::lib::subscriber::{Subscriber, Friend};
+use crate::lib::trade::now;
+
+// Half-days when Timmy and Tommy buy turnips, in order
+pub const PERIODS: &[&str] = &[
+	"mon_am", "mon_pm", "tue_am", "tue_pm", "wed_am", "wed_pm",
+	"thu_am", "thu_pm", "fri_am", "fri_pm", "sat_am", "sat_pm",
+];
+
+// In-game bounds, Daisy Mae sells at 90-110 and the best spike is 6x
+pub const MIN_BUY: u32 = 90;
+pub const MAX_BUY: u32 = 110;
+pub const MIN_SELL: u32 = 9;
+pub const MAX_SELL: u32 = 660;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TurnipWeek {
+	// Date of the Sunday that starts the week
+	pub week: String,
+	pub buy: Option<u32>,
+	// One price per entry of PERIODS
+	pub sell: Vec<Option<u32>>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TurnipPrice {
+	// "buy" or one of PERIODS, the current half-day if missing
+	pub period: Option<String>,
+	// A missing price clears the period
+	pub price: Option<u32>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Gates {
+	pub dodo: String,
+	// How long the gates stay open, an hour if missing
+	pub minutes: Option<u64>,
+	pub expires: Option<u64>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BoardEntry {
+	pub friend: Friend,
+	pub period: String,
+	pub price: u32,
+	pub gates_open: bool,
+	// Only while the gates are open
+	pub dodo: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct TurnipError {
+	pub details: String
+}
+
+impl TurnipError {
+	pub(crate) fn new(msg: &str) -> TurnipError {
+		TurnipError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for TurnipError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for TurnipError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+/*
+ * The week and period the island is in right now,
+ * Sunday is the buy day and AM ends at noon
+ */
+pub fn current_period(timezone: &Tz) -> (String, String) {
+	let local = Utc::now().with_timezone(timezone);
+	let date = local.date().naive_local();
+	let sunday = date - Duration::days(date.weekday().num_days_from_sunday() as i64);
+	let period = match date.weekday() {
+		Weekday::Sun => "buy".to_string(),
+		weekday => {
+			let index = (weekday.num_days_from_monday() * 2) as usize;
+			if local.hour() < 12 {
+				PERIODS[index].to_string()
+			} else {
+				PERIODS[index + 1].to_string()
+			}
+		}
+	};
+	(sunday.format("%Y-%m-%d").to_string(), period)
+}
+
+fn parse_week(week: &String) -> Result<NaiveDate, TurnipError> {
+	match NaiveDate::parse_from_str(week, "%Y-%m-%d") {
+		Ok(date) if date.weekday() == Weekday::Sun => Ok(date),
+		_ => Err(TurnipError::new("Weeks start on a Sunday (YYYY-MM-DD)"))
+	}
+}
+
+impl TurnipWeek {
+
+	fn key(subscriber_id: &String, week: &String) -> String {
+		[subscriber_id.to_owned(), ":turnips:".to_string(), week.to_owned()].concat()
+	}
+
+	fn index_key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":turnips".to_string()].concat()
+	}
+
+	/*
+	 * Gets the prices of a week, periods not logged are empty
+	 */
+	pub fn get(redis: &DbConn, subscriber_id: &String, week: &String) -> Result<TurnipWeek, TurnipError> {
+		parse_week(week)?;
+		match redis.hgetall::<_, HashMap<String, u32>>(TurnipWeek::key(subscriber_id, week)) {
+			Ok(value) => {
+				Ok(TurnipWeek {
+					week: week.to_owned(),
+					buy: value.get("buy").cloned(),
+					sell: PERIODS.iter().map(|period| value.get(*period).cloned()).collect()
+				})
+			},
+			Err(_e) => Err(TurnipError::new("Prices not found"))
+		}
+	}
+
+	/*
+	 * Lists the weeks with logged prices, newest first
+	 */
+	pub fn list(redis: &DbConn, subscriber_id: &String) -> Result<Vec<String>, TurnipError> {
+		match redis.zrevrange::<_, Vec<String>>(TurnipWeek::index_key(subscriber_id), 0, -1) {
+			Ok(weeks) => Ok(weeks),
+			Err(_e) => Err(TurnipError::new("Weeks not found"))
+		}
+	}
+
+	/*
+	 * Records or corrects the price of a period
+	 */
+	pub fn set_price(
+					redis: &DbConn,
+					subscriber_id: &String,
+					week: &String,
+					period: &String,
+					price: Option<u32>
+				) -> Result<(), TurnipError> {
+		let date = parse_week(week)?;
+		let (min, max) = if period == "buy" {
+			(MIN_BUY, MAX_BUY)
+		} else if PERIODS.contains(&period.as_str()) {
+			(MIN_SELL, MAX_SELL)
+		} else {
+			return Err(TurnipError::new("Invalid period"))
+		};
+		let key = TurnipWeek::key(subscriber_id, week);
+		let result = match price {
+			Some(price) => {
+				if price < min || price > max {
+					return Err(TurnipError::new("Price out of bounds"))
+				}
+				pipe()
+					.hset(&key, period, price)
+					.ignore()
+					.zadd(TurnipWeek::index_key(subscriber_id), week, date.and_hms(0, 0, 0).timestamp())
+					.ignore()
+					.query::<()>(&**redis)
+			},
+			None => redis.hdel::<_,_,()>(&key, period)
+		};
+		match result {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(TurnipError::new("unknown error"))
+		}
+	}
+}
+
+impl Gates {
+
+	fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":gates".to_string()].concat()
+	}
+
+	/*
+	 * Opens the island gates with a dodo code until they expire
+	 */
+	pub fn open(&mut self, redis: &DbConn, subscriber_id: &String) -> Result<(), TurnipError> {
+		self.dodo = self.dodo.to_uppercase();
+		if self.dodo.len() != 5 || !self.dodo.chars().all(|c| c.is_ascii_alphanumeric()) {
+			return Err(TurnipError::new("Invalid dodo code"))
+		}
+		let minutes = self.minutes.unwrap_or(60);
+		if minutes == 0 || minutes > 24 * 60 {
+			return Err(TurnipError::new("Invalid duration"))
+		}
+		let expires = now() + minutes * 60;
+		let key = Gates::key(subscriber_id);
+		match pipe()
+				.atomic()
+				.hset_multiple(&key, &[
+									("dodo", self.dodo.to_owned()),
+									("expires", expires.to_string()),
+								])
+				.ignore()
+				.expire_at(&key, expires as usize)
+				.ignore()
+				.query::<()>(&**redis) {
+			Ok(_) => {
+				self.minutes = Some(minutes);
+				self.expires = Some(expires);
+				Ok(())
+			},
+			Err(_e) => Err(TurnipError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Closes the gates before they expire
+	 */
+	pub fn close(redis: &DbConn, subscriber_id: &String) -> Result<(), TurnipError> {
+		match redis.del::<_,()>(Gates::key(subscriber_id)) {
+			Ok(_) => Ok(()),
+			Err(_e) => Err(TurnipError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Gets the gates if they are open
+	 */
+	pub fn get(redis: &DbConn, subscriber_id: &String) -> Option<Gates> {
+		match redis.hgetall::<_, HashMap<String, String>>(Gates::key(subscriber_id)) {
+			Ok(value) => Gates::from_hash(&value),
+			Err(_e) => None
+		}
+	}
+
+	fn from_hash(value: &HashMap<String, String>) -> Option<Gates> {
+		let expires = value.get("expires").and_then(|e| e.parse::<u64>().ok())?;
+		if expires <= now() {
+			return None
+		}
+		Some(Gates {
+			dodo: value.get("dodo").cloned()?,
+			minutes: None,
+			expires: Some(expires)
+		})
+	}
+}
+
+impl BoardEntry {
+
+	/*
+	 * Current sell price on each friend's island, best first.
+	 * Every friend is on their own timezone and Sundays have no sell price.
+	 */
+	pub fn for_subscriber(redis: &DbConn, subscriber: &mut Subscriber) -> Result<Vec<BoardEntry>, TurnipError> {
+		let friends = match subscriber.get_friends(redis) {
+			Ok(friends) => friends,
+			Err(e) => return Err(TurnipError::new(&e.details))
+		};
+		if friends.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for friend in &friends {
+			query.hget([friend.id.to_owned(), ":profile".to_string()].concat(), "timezone");
+		}
+		let timezones = match query.query::<Vec<Option<String>>>(&**redis) {
+			Ok(timezones) => timezones,
+			Err(_e) => return Err(TurnipError::new("unknown error"))
+		};
+		// Where each island is in its week, UTC if the friend never set a timezone
+		let islands: Vec<(Friend, String, String)> = friends.into_iter()
+			.zip(timezones)
+			.map(|(friend, timezone)| {
+				let timezone = timezone.and_then(|timezone| timezone.parse::<Tz>().ok()).unwrap_or(Tz::UTC);
+				let (week, period) = current_period(&timezone);
+				(friend, week, period)
+			})
+			.filter(|(_, _, period)| period != "buy")
+			.collect();
+		if islands.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for (friend, week, period) in &islands {
+			query.hget(TurnipWeek::key(&friend.id, week), period)
+				.hgetall(Gates::key(&friend.id));
+		}
+		let values = match query.query::<Vec<(Option<u32>, HashMap<String, String>)>>(&**redis) {
+			Ok(values) => values,
+			Err(_e) => return Err(TurnipError::new("unknown error"))
+		};
+		let mut board: Vec<BoardEntry> = Vec::new();
+		for ((friend, _, period), (price, gates)) in islands.into_iter().zip(values) {
+			let price = match price {
+				Some(price) => price,
+				None => continue
+			};
+			let gates = Gates::from_hash(&gates);
+			board.push(BoardEntry {
+							friend: friend,
+							period: period,
+							price: price,
+							gates_open: gates.is_some(),
+							dodo: gates.map(|gates| gates.dodo)
+						});
+		}
+		board.sort_by(|a, b| b.price.cmp(&a.price));
+		Ok(board)
+	}
+}
\ No newline at end of file
@@ -0,0 +1,218 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+use chrono::{NaiveDate, Utc};
+
+use crate::lib::catalog::Catalog;
+use crate::lib::subscriber::{Subscriber, Friend};
+
+// "boxes" villagers are still on the island, packing to move out
+pub const STATUSES: &[&str] = &["island", "boxes", "moved_out"];
+const MAX_RESIDENTS: usize = 10;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Resident {
+	pub villager: String,
+	pub status: String,
+	// Dates as YYYY-MM-DD
+	pub moved_in: Option<String>,
+	pub moved_out: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct VillagerHolder {
+	pub friend: Friend,
+	pub status: String,
+}
+
+#[derive(Debug)]
+pub struct VillagerError {
+	pub details: String
+}
+
+impl VillagerError {
+	fn new(msg: &str) -> VillagerError {
+		VillagerError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for VillagerError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for VillagerError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+fn valid_date(date: &Option<String>) -> bool {
+	match date {
+		Some(date) => NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok(),
+		None => true
+	}
+}
+
+impl Resident {
+
+	fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":villagers".to_string()].concat()
+	}
+
+	/*
+	 * Gets the villagers living on the island and the ones who left
+	 */
+	pub fn get_all(redis: &DbConn, subscriber_id: &String) -> Result<Vec<Resident>, VillagerError> {
+		match redis.hgetall::<
+								String,
+								Vec<(String, String)>
+							>(Resident::key(subscriber_id)) {
+				Ok(value) => {
+					let mut residents: Vec<Resident> = Vec::new();
+					for val in value {
+						if let Ok(resident) = serde_json::from_str::<Resident>(&val.1) {
+							residents.push(resident);
+						}
+					}
+					return Ok(residents)
+				},
+				Err(_e) => Err(VillagerError::new("Villagers not found"))
+		}
+	}
+
+	/*
+	 * Adds or updates a villager, an island fits ten of them
+	 */
+	pub fn set(&mut self, redis: &DbConn, subscriber_id: &String, catalog: &Catalog) -> Result<(), VillagerError> {
+		if catalog.villager(&self.villager).is_none() {
+			return Err(VillagerError::new("Unknown villager"))
+		}
+		if !STATUSES.contains(&self.status.as_str()) {
+			return Err(VillagerError::new("Invalid status"))
+		}
+		if !valid_date(&self.moved_in) || !valid_date(&self.moved_out) {
+			return Err(VillagerError::new("Invalid date"))
+		}
+		if self.status == "moved_out" {
+			if self.moved_out.is_none() {
+				self.moved_out = Some(Utc::today().naive_utc().format("%Y-%m-%d").to_string());
+			}
+		} else {
+			self.moved_out = None;
+			let living = Resident::get_all(redis, subscriber_id)?
+				.iter()
+				.filter(|resident| resident.villager != self.villager && resident.status != "moved_out")
+				.count();
+			if living >= MAX_RESIDENTS {
+				return Err(VillagerError::new("Island is full"))
+			}
+		}
+		let value = serde_json::to_string(&self).unwrap();
+		match redis.hset::<_,_,_,bool>(Resident::key(subscriber_id), &self.villager, value) {
+			Ok(_value) => return Ok(()),
+			Err(_e) => Err(VillagerError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Forgets a villager
+	 */
+	pub fn delete(redis: &DbConn, subscriber_id: &String, villager: &String) -> Result<(), VillagerError> {
+		match redis.hdel::<_,_,bool>(Resident::key(subscriber_id), villager) {
+			Ok(_value) => return Ok(()),
+			Err(_e) => Err(VillagerError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Friends who have a villager on their island, optionally
+	 * only the ones with a given status like "boxes"
+	 */
+	pub fn friends_with(
+						redis: &DbConn,
+						subscriber: &mut Subscriber,
+						villager: &String,
+						status: Option<String>
+					) -> Result<Vec<VillagerHolder>, VillagerError> {
+		let friends = match subscriber.get_friends(redis) {
+			Ok(friends) => friends,
+			Err(e) => return Err(VillagerError::new(&e.details))
+		};
+		let friends = match subscriber.visible_friends(redis, friends) {
+			Ok(friends) => friends,
+			Err(e) => return Err(VillagerError::new(&e.details))
+		};
+		if friends.is_empty() {
+			return Ok(Vec::new())
+		}
+		let mut query = pipe();
+		for friend in &friends {
+			query.hget(Resident::key(&friend.id), villager);
+		}
+		match query.query::<Vec<Option<String>>>(&**redis) {
+			Ok(value) => {
+				let mut holders: Vec<VillagerHolder> = Vec::new();
+				for (friend, resident) in friends.into_iter().zip(value) {
+					let resident = match resident.and_then(|r| serde_json::from_str::<Resident>(&r).ok()) {
+						Some(resident) => resident,
+						None => continue
+					};
+					if resident.status == "moved_out" {
+						continue;
+					}
+					if status.as_ref().map_or(true, |status| status == &resident.status) {
+						holders.push(VillagerHolder {
+										friend: friend,
+										status: resident.status
+									});
+					}
+				}
+				return Ok(holders)
+			},
+			Err(_e) => Err(VillagerError::new("Villagers not found"))
+		}
+	}
+}
+
+pub struct Dreamies;
+
+impl Dreamies {
+
+	fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":dreamies".to_string()].concat()
+	}
+
+	/*
+	 * Gets the villagers the subscriber dreams of
+	 */
+	pub fn get(redis: &DbConn, subscriber_id: &String) -> Result<Vec<String>, VillagerError> {
+		match redis.smembers::<_, Vec<String>>(Dreamies::key(subscriber_id)) {
+			Ok(mut value) => {
+				value.sort();
+				Ok(value)
+			},
+			Err(_e) => Err(VillagerError::new("Dreamies not found"))
+		}
+	}
+
+	pub fn add(redis: &DbConn, subscriber_id: &String, villager: &String, catalog: &Catalog) -> Result<(), VillagerError> {
+		if catalog.villager(villager).is_none() {
+			return Err(VillagerError::new("Unknown villager"))
+		}
+		match redis.sadd::<_,_,bool>(Dreamies::key(subscriber_id), villager) {
+			Ok(_value) => Ok(()),
+			Err(_e) => Err(VillagerError::new("unknown error"))
+		}
+	}
+
+	pub fn delete(redis: &DbConn, subscriber_id: &String, villager: &String) -> Result<(), VillagerError> {
+		match redis.srem::<_,_,bool>(Dreamies::key(subscriber_id), villager) {
+			Ok(_value) => Ok(()),
+			Err(_e) => Err(VillagerError::new("unknown error"))
+		}
+	}
+}
@@ -0,0 +1,148 @@
+use crate::DbConn;
+use rocket_contrib::databases::redis::{ Commands, pipe, PipelineCommands};
+use serde::{Deserialize, Serialize};
+use std::error;
+use std::fmt;
+
+use crate::lib::class::{Class, Item};
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WishlistItem {
+	pub class: String,
+	pub id: String,
+	// 1 is the lowest priority and 5 the highest
+	#[serde(default = "default_priority")]
+	pub priority: u8,
+	#[serde(default)]
+	pub note: String,
+}
+
+fn default_priority() -> u8 {
+	1
+}
+
+#[derive(Debug)]
+pub struct WishlistError {
+	pub details: String
+}
+
+impl WishlistError {
+	fn new(msg: &str) -> WishlistError {
+		WishlistError{details: msg.to_string()}
+	}
+}
+
+impl fmt::Display for WishlistError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f,"{}",self.details)
+	}
+}
+
+impl error::Error for WishlistError {
+	fn description(&self) -> &str {
+		&self.details
+	}
+}
+
+pub struct Wishlist;
+
+impl Wishlist {
+
+	fn key(subscriber_id: &String) -> String {
+		[subscriber_id.to_owned(), ":wishlist".to_string()].concat()
+	}
+
+	fn field(class: &String, id: &String) -> String {
+		[class.to_owned(), ":".to_string(), id.to_owned()].concat()
+	}
+
+	/*
+	 * Gets the wishlist, highest priority first
+	 */
+	pub fn get(redis: &DbConn, subscriber_id: &String) -> Result<Vec<WishlistItem>, WishlistError> {
+		match redis.hgetall::<
+								String,
+								Vec<(String, String)>
+							>(Wishlist::key(subscriber_id)) {
+				Ok(value) => {
+					let mut items: Vec<WishlistItem> = Vec::new();
+					for val in value {
+						if let Ok(item) = serde_json::from_str::<WishlistItem>(&val.1) {
+							items.push(item);
+						}
+					}
+					items.sort_by(|a, b| b.priority.cmp(&a.priority));
+					return Ok(items)
+				},
+				Err(_e) => Err(WishlistError::new("Wishlist not found"))
+		}
+	}
+
+	/*
+	 * Adds an item to the wishlist or updates its priority and note
+	 */
+	pub fn set(redis: &DbConn, subscriber_id: &String, item: WishlistItem) -> Result<(), WishlistError> {
+		if !Class(item.class.to_owned()).is_valid() || item.id.is_empty() {
+			return Err(WishlistError::new("Invalid item"))
+		}
+		if item.priority < 1 || item.priority > 5 {
+			return Err(WishlistError::new("Invalid priority"))
+		}
+		let value = serde_json::to_string(&item).unwrap();
+		match redis.hset::<_,_,_,bool>(Wishlist::key(subscriber_id), Wishlist::field(&item.class, &item.id), value) {
+			Ok(_value) => return Ok(()),
+			Err(_e) => Err(WishlistError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Removes an item from the wishlist
+	 */
+	pub fn delete(redis: &DbConn, subscriber_id: &String, class: &String, id: &String) -> Result<(), WishlistError> {
+		match redis.hdel::<_,_,bool>(Wishlist::key(subscriber_id), Wishlist::field(class, id)) {
+			Ok(_value) => return Ok(()),
+			Err(_e) => Err(WishlistError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Adds one subscriber's wishlist to another's, items already on
+	 * it keep their priority and note
+	 */
+	pub(crate) fn merge(redis: &DbConn, from: &String, into: &String) -> Result<u32, WishlistError> {
+		let fields = match redis.hgetall::<_, Vec<(String, String)>>(Wishlist::key(from)) {
+			Ok(fields) => fields,
+			Err(_e) => return Err(WishlistError::new("Wishlist not found"))
+		};
+		if fields.is_empty() {
+			return Ok(0)
+		}
+		let key = Wishlist::key(into);
+		let mut query = pipe();
+		query.atomic();
+		for (field, value) in &fields {
+			query.hset_nx(&key, field, value).ignore();
+		}
+		match query.query::<()>(&**redis) {
+			Ok(_) => Ok(fields.len() as u32),
+			Err(_e) => Err(WishlistError::new("unknown error"))
+		}
+	}
+
+	/*
+	 * Items that arrived in stock are no longer wished for
+	 */
+	pub fn fulfil(redis: &DbConn, subscriber_id: &String, class: &String, items: &Vec<Item>) -> Result<(), WishlistError> {
+		let fields: Vec<String> = items.iter()
+			.filter(|item| item.stock > 0)
+			.map(|item| Wishlist::field(class, &item.id))
+			.collect();
+		if fields.is_empty() {
+			return Ok(())
+		}
+		match redis.hdel::<_,_,u32>(Wishlist::key(subscriber_id), fields) {
+			Ok(_value) => return Ok(()),
+			Err(_e) => Err(WishlistError::new("unknown error"))
+		}
+	}
+}
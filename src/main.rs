@@ -1,28 +1,20 @@
 #![feature(proc_macro_hygiene, decl_macro)] 
 
 #[macro_use] extern crate rocket;
-#[macro_use] extern crate rocket_contrib;
 use rocket::Rocket; 
-extern crate serde;
-extern crate google_signin;
 extern crate dotenv;
 extern crate rocket_cors;
+extern crate crossing_api;
 use rocket_cors::{Error};
 
 use dotenv::dotenv;
 
-use rocket_contrib::databases::redis::{self};
-
-mod lib;
-
-#[cfg(test)] mod tests;
-
-#[database("cross_db")]
-pub struct DbConn(redis::Connection);
+use crossing_api::{lib, DbConn};
 
 fn rocket() -> Rocket {
 	rocket::ignite()
 		.attach(DbConn::fairing())
+		.manage(lib::LiveCatalog::new(lib::Catalog::load().expect("Catalog couldn't be loaded")))
 		.mount("/", routes![
 							lib::api::subscriber_register,
 							lib::api::get_class,
@@ -35,6 +27,88 @@ fn rocket() -> Rocket {
 							lib::api::get_profile,
 							lib::api::get_other_profile,
 							lib::api::post_profile,
+							lib::api::get_trade_suggestions,
+							lib::api::post_trade,
+							lib::api::get_trades,
+							lib::api::get_trade,
+							lib::api::update_trade,
+							lib::api::search_item,
+							lib::api::get_wishlist,
+							lib::api::get_other_wishlist,
+							lib::api::post_wishlist,
+							lib::api::delete_wishlist,
+							lib::api::get_listings,
+							lib::api::get_my_listings,
+							lib::api::get_listing,
+							lib::api::post_listing,
+							lib::api::update_listing,
+							lib::api::delete_listing,
+							lib::api::get_turnips,
+							lib::api::get_turnip_weeks,
+							lib::api::get_turnip_week,
+							lib::api::post_turnips,
+							lib::api::update_turnips,
+							lib::api::get_turnip_prediction,
+							lib::api::get_turnip_board,
+							lib::api::post_gates,
+							lib::api::delete_gates,
+							lib::api::post_queue,
+							lib::api::get_hosted_queue,
+							lib::api::get_queue,
+							lib::api::delete_queue,
+							lib::api::join_queue,
+							lib::api::leave_queue,
+							lib::api::advance_queue,
+							lib::api::get_villagers,
+							lib::api::get_villager_friends,
+							lib::api::get_residents,
+							lib::api::post_resident,
+							lib::api::delete_resident,
+							lib::api::get_dreamies,
+							lib::api::post_dreamie,
+							lib::api::delete_dreamie,
+							lib::api::get_recipes,
+							lib::api::get_my_recipes,
+							lib::api::post_recipes,
+							lib::api::learn_recipes,
+							lib::api::unlearn_recipes,
+							lib::api::get_recipe_stats,
+							lib::api::get_friend_spares,
+							lib::api::get_spares,
+							lib::api::get_events,
+							lib::api::get_my_events,
+							lib::api::get_needed_events,
+							lib::api::get_checklist,
+							lib::api::toggle_checklist,
+							lib::api::post_checklist,
+							lib::api::delete_checklist,
+							lib::api::get_leaderboard,
+							lib::api::get_friends_leaderboard,
+							lib::api::get_class_leaderboard,
+							lib::api::get_class_friends_leaderboard,
+							lib::api::get_achievements,
+							lib::api::get_history,
+							lib::api::get_snapshots,
+							lib::api::post_snapshot,
+							lib::api::get_snapshot_diff,
+							lib::api::restore_snapshot,
+							lib::api::delete_snapshot,
+							lib::api::post_sync,
+							lib::api::get_inventory,
+							lib::api::get_other_inventory,
+							lib::api::get_export,
+							lib::api::post_import,
+							lib::api::delete_subscriber,
+							lib::api::get_data_export,
+							lib::api::get_data_export_status,
+							lib::api::admin_search,
+							lib::api::admin_lookup,
+							lib::api::admin_suspend,
+							lib::api::admin_unsuspend,
+							lib::api::admin_reset_name,
+							lib::api::admin_set_role,
+							lib::api::admin_reload_catalog,
+							lib::api::admin_get_audit,
 							])
 }
 
@@ -0,0 +1,273 @@
+use crate::lib::{predict, Pattern, PatternPrediction, PriceRange};
+use crate::lib::{csv_field, csv_records, slug, class_name, Export};
+use crate::lib::repair_meta;
+use crate::lib::HistoryQuery;
+
+fn probability(predictions: &Vec<PatternPrediction>, pattern: Pattern) -> f64 {
+	predictions.iter()
+		.find(|prediction| prediction.pattern == pattern)
+		.map(|prediction| prediction.probability)
+		.unwrap_or(0.0)
+}
+
+#[test]
+fn empty_week_uses_priors() {
+	let predictions = predict(Some(100), &[None; 12], Some(Pattern::Decreasing)).unwrap();
+	assert_eq!(predictions.len(), 4);
+	assert!((probability(&predictions, Pattern::LargeSpike) - 0.45).abs() < 0.0001);
+	assert!((probability(&predictions, Pattern::Decreasing) - 0.05).abs() < 0.0001);
+}
+
+#[test]
+fn decreasing_week() {
+	let sell = [Some(88), Some(84), Some(80), Some(76), Some(72), Some(68),
+				Some(64), Some(60), Some(56), Some(52), Some(48), Some(44)];
+	let predictions = predict(Some(100), &sell, None).unwrap();
+	assert_eq!(predictions.len(), 1);
+	assert_eq!(predictions[0].pattern, Pattern::Decreasing);
+	assert!((predictions[0].probability - 1.0).abs() < 0.0001);
+}
+
+#[test]
+fn large_spike_week() {
+	let sell = [Some(88), Some(84), Some(120), Some(180), Some(500), None,
+				None, None, None, None, None, None];
+	let predictions = predict(Some(100), &sell, None).unwrap();
+	assert_eq!(predictions.len(), 1);
+	assert_eq!(predictions[0].pattern, Pattern::LargeSpike);
+	assert_eq!(predictions[0].ranges[4], PriceRange { min: 500, max: 500 });
+	assert_eq!(predictions[0].ranges[5], PriceRange { min: 140, max: 200 });
+	assert_eq!(predictions[0].ranges[6], PriceRange { min: 90, max: 140 });
+	assert_eq!(predictions[0].ranges[11], PriceRange { min: 40, max: 90 });
+}
+
+#[test]
+fn high_monday_is_fluctuating_or_small_spike() {
+	let mut sell = [None; 12];
+	sell[0] = Some(130);
+	let predictions = predict(Some(100), &sell, None).unwrap();
+	assert!(probability(&predictions, Pattern::Fluctuating) > 0.0);
+	assert!(probability(&predictions, Pattern::SmallSpike) > 0.0);
+	assert_eq!(probability(&predictions, Pattern::LargeSpike), 0.0);
+	assert_eq!(probability(&predictions, Pattern::Decreasing), 0.0);
+}
+
+#[test]
+fn small_spike_week() {
+	// Peak starting on Tuesday AM with a top rate of 1.9
+	let sell = [Some(85), Some(81), Some(95), Some(130), Some(170), Some(190),
+				Some(175), None, None, None, None, None];
+	let predictions = predict(Some(100), &sell, None).unwrap();
+	assert_eq!(predictions.len(), 1);
+	assert_eq!(predictions[0].pattern, Pattern::SmallSpike);
+	assert!(predictions[0].ranges[7].max <= 90);
+}
+
+#[test]
+fn unknown_buy_price() {
+	let mut sell = [None; 12];
+	sell[0] = Some(80);
+	let predictions = predict(None, &sell, None).unwrap();
+	let total: f64 = predictions.iter().map(|prediction| prediction.probability).sum();
+	assert!((total - 1.0).abs() < 0.0001);
+	assert_eq!(probability(&predictions, Pattern::Fluctuating) > 0.0, true);
+}
+
+#[test]
+fn impossible_week() {
+	let mut sell = [None; 12];
+	sell[0] = Some(600);
+	assert!(predict(Some(100), &sell, None).is_none());
+}
+
+/*
+ * Whole weeks the game's price code (the port linked from the predictor)
+ * gives for a few seeds, so the model is checked against the game itself
+ */
+fn game_week(prices: [u32; 12]) -> [Option<u32>; 12] {
+	let mut sell = [None; 12];
+	for (sell, price) in sell.iter_mut().zip(prices.iter()) {
+		*sell = Some(*price);
+	}
+	sell
+}
+
+fn only_pattern(buy: u32, prices: [u32; 12]) -> Pattern {
+	let sell = game_week(prices);
+	// Half a week in, the right pattern has to still be on the table
+	let monday_to_wednesday: Vec<Option<u32>> = sell.iter()
+		.enumerate()
+		.map(|(i, price)| if i < 6 { *price } else { None })
+		.collect();
+	let predictions = predict(Some(buy), &sell, None).unwrap();
+	assert_eq!(predictions.len(), 1);
+	let partial = predict(Some(buy), &monday_to_wednesday, None).unwrap();
+	assert!(probability(&partial, predictions[0].pattern) > 0.0);
+	predictions[0].pattern
+}
+
+#[test]
+fn game_fluctuating_week() {
+	// Seed 1
+	let prices = [99, 117, 127, 114, 123, 65, 58, 120, 65, 61, 53, 99];
+	assert_eq!(only_pattern(96, prices), Pattern::Fluctuating);
+}
+
+#[test]
+fn game_large_spike_week() {
+	// Seed 2
+	let prices = [97, 93, 90, 125, 184, 444, 174, 142, 60, 47, 90, 63];
+	assert_eq!(only_pattern(110, prices), Pattern::LargeSpike);
+}
+
+#[test]
+fn game_decreasing_week() {
+	// Seed 4
+	let prices = [94, 89, 85, 82, 78, 75, 70, 65, 62, 58, 54, 50];
+	assert_eq!(only_pattern(107, prices), Pattern::Decreasing);
+}
+
+#[test]
+fn game_small_spike_week() {
+	// Seed 10
+	let prices = [53, 49, 45, 40, 37, 96, 112, 132, 136, 131, 50, 47];
+	assert_eq!(only_pattern(91, prices), Pattern::SmallSpike);
+}
+
+#[test]
+fn csv_fields_are_quoted_when_needed() {
+	assert_eq!(csv_field("sea-bass"), "sea-bass");
+	assert_eq!(csv_field("Tom, Nook"), "\"Tom, Nook\"");
+	assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+	assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+}
+
+#[test]
+fn csv_records_handle_bom_and_crlf() {
+	let records = csv_records("\u{feff}kind,class\r\nitem,fish\r\n");
+	assert_eq!(records, vec![vec!["kind", "class"], vec!["item", "fish"]]);
+}
+
+#[test]
+fn csv_records_handle_quotes() {
+	let records = csv_records("a,\"b,c\",\"d\"\"e\"\n\"multi\nline\",x");
+	assert_eq!(records, vec![vec!["a", "b,c", "d\"e"], vec!["multi\nline", "x"]]);
+}
+
+#[test]
+fn csv_records_skip_blank_lines() {
+	let records = csv_records("a\n\n,\nb");
+	assert_eq!(records, vec![vec!["a"], vec!["b"]]);
+}
+
+#[test]
+fn spreadsheet_names_become_ids() {
+	assert_eq!(slug("Sea Bass", '-'), "sea-bass");
+	assert_eq!(slug("  Giant_Snakehead ", '-'), "giant-snakehead");
+	assert_eq!(slug("Mahi-mahi (Dorado)", '-'), "mahi-mahi-dorado");
+	assert_eq!(slug("Bass - ", '-'), "bass");
+	assert_eq!(slug("Sea Creatures", '_'), "sea_creatures");
+}
+
+#[test]
+fn spreadsheet_categories_use_aliases() {
+	assert_eq!(class_name("Insects"), "bugs");
+	assert_eq!(class_name("Deep-Sea Creatures"), "sea");
+	assert_eq!(class_name("Fish"), "fish");
+}
+
+#[test]
+fn native_csv_import() {
+	let text = "kind,class,id,donated,stock,value\r\n\
+				profile,,name,,,Nook\r\n\
+				friend,,abc,,,\"Tom, Nook\"\r\n\
+				item,fish,sea-bass,true,3,\r\n\
+				item,fish,koi,false,x,\r\n";
+	let (export, skipped) = Export::from_csv(text).unwrap();
+	let profile = export.profile.unwrap();
+	assert_eq!(profile.name, "Nook");
+	assert!(profile.visibility.is_none());
+	assert_eq!(export.friends.len(), 1);
+	assert_eq!(export.friends[0].name, "Tom, Nook");
+	assert_eq!(export.classes["fish"].len(), 1);
+	assert_eq!(export.classes["fish"][0].id, "sea-bass");
+	assert!(export.classes["fish"][0].donated);
+	assert_eq!(export.classes["fish"][0].stock, 3);
+	assert_eq!(skipped, vec!["line 5: invalid stock"]);
+	assert!(!export.keep_donated && !export.keep_stock);
+}
+
+#[test]
+fn spreadsheet_csv_import() {
+	let text = "\u{feff}Category,Name,Donated\r\nInsects,Common Butterfly,✓\r\nFish,Sea Bass,\r\n";
+	let (export, skipped) = Export::from_csv(text).unwrap();
+	assert!(skipped.is_empty());
+	assert_eq!(export.classes["bugs"][0].id, "common-butterfly");
+	assert!(export.classes["bugs"][0].donated);
+	assert_eq!(export.classes["fish"][0].id, "sea-bass");
+	assert!(!export.classes["fish"][0].donated);
+	// No quantity column, the stored stock stays
+	assert!(export.keep_stock);
+	assert!(!export.keep_donated);
+}
+
+#[test]
+fn csv_import_needs_known_columns() {
+	match Export::from_csv("") {
+		Err(e) => assert_eq!(e.details, "Empty file"),
+		Ok(_) => panic!("empty file was read")
+	}
+	match Export::from_csv("foo,bar\n1,2\n") {
+		Err(e) => assert_eq!(e.details, "Unknown columns"),
+		Ok(_) => panic!("unknown columns were read")
+	}
+}
+
+#[test]
+fn repair_meta_normalizes_values() {
+	assert_eq!(repair_meta("True: 3"), Some("true:3".to_string()));
+	assert_eq!(repair_meta("yes:2"), Some("true:2".to_string()));
+	assert_eq!(repair_meta("0:4"), Some("false:4".to_string()));
+	assert_eq!(repair_meta("true:"), Some("true:0".to_string()));
+	assert_eq!(repair_meta(":7"), Some("false:7".to_string()));
+}
+
+#[test]
+fn repair_meta_fills_the_missing_half() {
+	// A lone number other than 0 or 1 is the stock
+	assert_eq!(repair_meta("5"), Some("false:5".to_string()));
+	assert_eq!(repair_meta("1"), Some("true:0".to_string()));
+	assert_eq!(repair_meta("0"), Some("false:0".to_string()));
+	assert_eq!(repair_meta("no"), Some("false:0".to_string()));
+	assert_eq!(repair_meta(""), Some("false:0".to_string()));
+}
+
+#[test]
+fn repair_meta_clamps_stock() {
+	assert_eq!(repair_meta("true:-4"), Some("true:0".to_string()));
+	assert_eq!(repair_meta("false:99999999999"), Some("false:4294967295".to_string()));
+}
+
+#[test]
+fn repair_meta_gives_up_on_garbage() {
+	assert_eq!(repair_meta("maybe:3"), None);
+	assert_eq!(repair_meta("true:lots"), None);
+	assert_eq!(repair_meta("true:1:2"), None);
+	assert_eq!(repair_meta("fish"), None);
+}
+
+#[test]
+fn history_range_is_in_milliseconds() {
+	let query = HistoryQuery { class: None, item: None, from: Some(10), to: Some(20), count: None };
+	assert_eq!(query.range().unwrap(), ("10000".to_string(), "20999".to_string()));
+	let query = HistoryQuery { class: None, item: None, from: None, to: None, count: None };
+	assert_eq!(query.range().unwrap(), ("-".to_string(), "+".to_string()));
+}
+
+#[test]
+fn history_range_rejects_overflowing_times() {
+	let query = HistoryQuery { class: None, item: None, from: Some(u64::max_value()), to: None, count: None };
+	assert!(query.range().is_err());
+	let query = HistoryQuery { class: None, item: None, from: None, to: Some(u64::max_value() / 1000), count: None };
+	assert!(query.range().is_err());
+}